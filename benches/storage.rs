@@ -0,0 +1,200 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use serde_json::json;
+use std::sync::Mutex;
+
+use conga::libs::{
+    storage::{memory::MemoryStorage, Storage},
+    structs::{Item, Meta},
+};
+
+// Items spread across the background queues, none of which are the queue under test
+const BACKGROUND_ITEMS: usize = 100_000;
+const BACKGROUND_QUEUES: usize = 100;
+// Items in the queue under test
+const TARGET_ITEMS: usize = 10;
+
+fn item(queue: &str, n: usize) -> Item {
+    Item {
+        queue: queue.to_string(),
+        content: json!({ "n": n }),
+        priority: None,
+        ttl: None,
+        delay_seconds: None,
+        deliver_at: None,
+        dedup_id: None,
+        exchange: None,
+        routing_key: None,
+        meta: Some(Meta {
+            id: n.to_string(),
+            received_epoch: 0,
+            receive_count: 0,
+            dead_letter_source: None,
+            expires_epoch: None,
+            deliver_epoch: None,
+            size: 0,
+            offset: None,
+        }),
+    }
+}
+
+// Queue operations under test, so the deque based storage can be compared against the single
+// `Vec` every queue was held in before
+trait Backend: Default {
+    fn push(&self, item: Item);
+    fn preview(&self, queue: &str) -> Vec<Item>;
+    fn count(&self, queue: &str) -> usize;
+    fn take(&self, queue: &str, limit: Option<usize>) -> Vec<Item>;
+}
+
+impl Backend for MemoryStorage {
+    fn push(&self, item: Item) {
+        Storage::push(self, item).unwrap()
+    }
+
+    fn preview(&self, queue: &str) -> Vec<Item> {
+        Storage::preview(self, queue, 0, None, None).unwrap()
+    }
+
+    fn count(&self, queue: &str) -> usize {
+        Storage::count(self, queue).unwrap()
+    }
+
+    fn take(&self, queue: &str, limit: Option<usize>) -> Vec<Item> {
+        Storage::take(self, queue, limit, None).unwrap()
+    }
+}
+
+// Baseline, every queue shares one lock and one `Vec`, which is scanned in full for any queue
+#[derive(Default)]
+struct VecStorage {
+    items: Mutex<Vec<Item>>,
+}
+
+impl Backend for VecStorage {
+    fn push(&self, item: Item) {
+        self.items.lock().unwrap().push(item)
+    }
+
+    fn preview(&self, queue: &str) -> Vec<Item> {
+        let items = self.items.lock().unwrap();
+        items
+            .iter()
+            .filter(|item| item.queue == queue)
+            .cloned()
+            .collect()
+    }
+
+    fn count(&self, queue: &str) -> usize {
+        let items = self.items.lock().unwrap();
+        items.iter().filter(|item| item.queue == queue).count()
+    }
+
+    fn take(&self, queue: &str, limit: Option<usize>) -> Vec<Item> {
+        let mut items = self.items.lock().unwrap();
+        let mut remaining = limit.unwrap_or(usize::MAX);
+        let mut taken = Vec::new();
+        let mut kept = Vec::with_capacity(items.len());
+        for item in items.drain(..) {
+            if item.queue == queue && remaining > 0 {
+                remaining -= 1;
+                taken.push(item);
+            } else {
+                kept.push(item);
+            }
+        }
+        *items = kept;
+        taken
+    }
+}
+
+// Storage holding a large backlog in other queues, as a busy server would
+fn busy_storage<B: Backend>() -> B {
+    let storage = B::default();
+    for n in 0..BACKGROUND_ITEMS {
+        storage.push(item(&format!("background-{}", n % BACKGROUND_QUEUES), n));
+    }
+    storage
+}
+
+fn fill_target<B: Backend>(storage: &B) {
+    for n in 0..TARGET_ITEMS {
+        storage.push(item("target", n));
+    }
+}
+
+fn bench_backend<B: Backend>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(name);
+
+    group.bench_function("push", |b| {
+        b.iter_batched(
+            B::default,
+            |storage| fill_target(&storage),
+            BatchSize::SmallInput,
+        )
+    });
+
+    let storage = busy_storage::<B>();
+    fill_target(&storage);
+    group.bench_function("preview_small_queue_of_busy_store", |b| {
+        b.iter(|| storage.preview("target"))
+    });
+    group.bench_function("count_small_queue_of_busy_store", |b| {
+        b.iter(|| storage.count("target"))
+    });
+    storage.take("target", None);
+
+    group.bench_function("take_small_queue_of_busy_store", |b| {
+        b.iter_batched(
+            || fill_target(&storage),
+            |_| storage.take("target", None),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.bench_function("take_large_queue", |b| {
+        b.iter_batched_ref(
+            busy_storage::<B>,
+            |storage| storage.take("background-0", None),
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.bench_function("take_oldest_of_large_queue", |b| {
+        b.iter_batched_ref(
+            busy_storage::<B>,
+            |storage| storage.take("background-0", Some(TARGET_ITEMS)),
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+fn bench_memory_storage(c: &mut Criterion) {
+    bench_backend::<MemoryStorage>(c, "memory_storage");
+}
+
+fn bench_vec_baseline(c: &mut Criterion) {
+    bench_backend::<VecStorage>(c, "vec_baseline");
+}
+
+criterion_group!(benches, bench_memory_storage, bench_vec_baseline);
+criterion_main!(benches);
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
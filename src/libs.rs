@@ -1,7 +1,19 @@
+pub mod dedup;
+pub mod definitions;
+pub mod events;
+pub mod exchanges;
+pub mod expiry;
+pub mod filter;
 pub mod middleware;
+pub mod notifier;
 pub mod routes;
+pub mod schemas;
+pub mod storage;
 pub mod structs;
+pub mod topics;
 pub mod utils;
+pub mod wal;
+pub mod websocket;
 
 /*
 ########################################################################################################
@@ -0,0 +1,91 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+
+// Default number of seconds a deduplication key is remembered for
+pub const DEFAULT_DEDUP_WINDOW: u64 = 300;
+
+// Deduplication keys of recently added items, so a producer retrying a request does not add an item twice
+#[derive(Default)]
+pub struct Dedup {
+    queues: Mutex<HashMap<String, QueueKeys>>,
+}
+
+// Keys remembered for a queue, alongside the order they expire in
+#[derive(Default)]
+struct QueueKeys {
+    ids: HashMap<String, (String, i64)>,
+    expiries: VecDeque<(i64, String)>,
+}
+
+impl QueueKeys {
+    // Forgets every key whose window has passed
+    fn prune(&mut self, now: i64) {
+        while let Some((expires_epoch, key)) = self.expiries.front() {
+            if *expires_epoch > now {
+                break;
+            }
+            // The key may have been released and claimed again since, with a later expiry
+            if self.ids.get(key).is_some_and(|(_, e)| e == expires_epoch) {
+                self.ids.remove(key);
+            }
+            self.expiries.pop_front();
+        }
+    }
+}
+
+impl Dedup {
+    // Remembers `key` as belonging to item `id` for `window` seconds, unless it is already remembered.
+    // Returns the id of the item the key already belongs to, if it does
+    pub fn claim(&self, queue: &str, key: &str, id: &str, window: u64) -> Option<String> {
+        let now = Utc::now().timestamp();
+        let mut queues = self.queues.lock().unwrap();
+        let keys = queues.entry(queue.to_string()).or_default();
+        keys.prune(now);
+
+        if let Some((id, _)) = keys.ids.get(key) {
+            return Some(id.clone());
+        }
+        let expires_epoch = now + window as i64;
+        keys.ids
+            .insert(key.to_string(), (id.to_string(), expires_epoch));
+        keys.expiries.push_back((expires_epoch, key.to_string()));
+        None
+    }
+
+    // Forgets a key claimed for item `id`, when the item was not added after all
+    pub fn release(&self, queue: &str, key: &str, id: &str) {
+        if let Some(keys) = self.queues.lock().unwrap().get_mut(queue) {
+            if keys.ids.get(key).is_some_and(|(claimed, _)| claimed == id) {
+                keys.ids.remove(key);
+            }
+        }
+    }
+}
+
+// Deduplication key of an item's content, the hex encoded SHA-256 digest of it
+pub fn content_key(content: &serde_json::Value) -> String {
+    hex::encode(Sha256::digest(content.to_string().as_bytes()))
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
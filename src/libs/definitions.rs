@@ -0,0 +1,144 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::PathBuf,
+    sync::{Mutex, RwLock},
+};
+
+use log::info;
+
+use crate::libs::structs::QueueConfig;
+
+// Default file definitions changed through the admin API are saved to
+pub const DEFAULT_DEFINITIONS_FILE: &str = "./data/queues.json";
+
+// Declared queues and their settings, from `[queues.<name>]` config sections and the admin API
+pub struct QueueDefinitions {
+    queues: RwLock<HashMap<String, QueueConfig>>,
+    // Every change made through the admin API, by queue. None marks a deleted definition
+    changes: Mutex<BTreeMap<String, Option<QueueConfig>>>,
+    path: PathBuf,
+}
+
+impl QueueDefinitions {
+    // Loads the queues declared in config, with any changes saved at `path` applied over them
+    pub fn load(
+        mut queues: HashMap<String, QueueConfig>,
+        path: PathBuf,
+    ) -> io::Result<QueueDefinitions> {
+        let changes: BTreeMap<String, Option<QueueConfig>> = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        for (name, config) in &changes {
+            match config {
+                Some(config) => queues.insert(name.clone(), config.clone()),
+                None => queues.remove(name),
+            };
+        }
+        if !changes.is_empty() {
+            info!(
+                "Applied {} queue definition changes from '{}'",
+                changes.len(),
+                path.display()
+            );
+        }
+
+        Ok(QueueDefinitions {
+            queues: RwLock::new(queues),
+            changes: Mutex::new(changes),
+            path,
+        })
+    }
+
+    // Reads a setting of a queue, None if the queue is not declared or the setting is not set
+    pub fn with<T>(
+        &self,
+        queue: &str,
+        setting: impl FnOnce(&QueueConfig) -> Option<T>,
+    ) -> Option<T> {
+        self.queues.read().unwrap().get(queue).and_then(setting)
+    }
+
+    // Reads a setting of every declared queue that has it set
+    pub fn each<T>(&self, setting: impl Fn(&QueueConfig) -> Option<T>) -> Vec<T> {
+        self.queues
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(setting)
+            .collect()
+    }
+
+    pub fn get(&self, queue: &str) -> Option<QueueConfig> {
+        self.queues.read().unwrap().get(queue).cloned()
+    }
+
+    pub fn contains(&self, queue: &str) -> bool {
+        self.queues.read().unwrap().contains_key(queue)
+    }
+
+    // Every declared queue, by name
+    pub fn all(&self) -> BTreeMap<String, QueueConfig> {
+        self.queues
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, config)| (name.clone(), config.clone()))
+            .collect()
+    }
+
+    // Declares a queue or replaces its settings, returning true if it was not declared before
+    pub fn set(&self, queue: &str, config: QueueConfig) -> io::Result<bool> {
+        Ok(self.change(queue, Some(config))?.is_none())
+    }
+
+    // Removes the declaration of a queue, returning false if it was not declared
+    pub fn remove(&self, queue: &str) -> io::Result<bool> {
+        if !self.contains(queue) {
+            return Ok(false);
+        }
+        Ok(self.change(queue, None)?.is_some())
+    }
+
+    // Saves a change so it is applied again on startup, then makes it, returning the queue's previous settings.
+    // Changes are made one at a time, so the saved changes always match the declared queues
+    fn change(&self, queue: &str, config: Option<QueueConfig>) -> io::Result<Option<QueueConfig>> {
+        let mut changes = self.changes.lock().unwrap();
+        let mut saved = changes.clone();
+        saved.insert(queue.to_string(), config.clone());
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&saved)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        *changes = saved;
+
+        let mut queues = self.queues.write().unwrap();
+        Ok(match config {
+            Some(config) => queues.insert(queue.to_string(), config),
+            None => queues.remove(queue),
+        })
+    }
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
@@ -0,0 +1,223 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_web::web::Bytes;
+use chrono::Utc;
+use futures_util::{stream, Stream, StreamExt as _};
+use serde::Serialize;
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::timeout,
+};
+
+// Default number of recent events kept per queue for clients resuming a stream
+pub const DEFAULT_EVENT_HISTORY: usize = 1000;
+// Most item ids an event lists, so large bulk requests do not fill the history with ids
+pub const MAX_EVENT_IDS: usize = 100;
+// Streams send a comment this often when idle, so dropped clients are noticed
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+// Kind of activity an event reports
+#[derive(Clone, Copy)]
+pub enum EventKind {
+    Added,
+    Fetched,
+    Expired,
+    Purged,
+    Dropped,
+}
+
+impl EventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Added => "added",
+            EventKind::Fetched => "fetched",
+            EventKind::Expired => "expired",
+            EventKind::Purged => "purged",
+            EventKind::Dropped => "dropped",
+        }
+    }
+}
+
+// Activity on a queue, numbered in the order it happened on that queue since the server started
+#[derive(Clone, Serialize)]
+pub struct QueueEvent {
+    #[serde(skip)]
+    pub id: u64,
+    #[serde(skip)]
+    pub kind: EventKind,
+    pub queue: String,
+    pub count: usize,
+    // Ids of the items involved, when they are known, up to `MAX_EVENT_IDS` of them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ids: Vec<String>,
+    pub epoch: i64,
+}
+
+impl QueueEvent {
+    // Encodes the event as a server-sent event, its id prefixed with when the server started so ids
+    // from before a restart are told apart
+    fn to_sse(&self, started: i64) -> Bytes {
+        Bytes::from(format!(
+            "id: {}-{}\nevent: {}\ndata: {}\n\n",
+            started,
+            self.id,
+            self.kind.as_str(),
+            serde_json::to_string(self).unwrap_or_default()
+        ))
+    }
+}
+
+// Recent and live events of a single queue
+struct QueueEvents {
+    next_id: u64,
+    history: VecDeque<QueueEvent>,
+    sender: broadcast::Sender<QueueEvent>,
+}
+
+// Publishes queue activity to event stream subscribers. Queues only have an entry once they have
+// had activity, or while something is subscribed to them
+pub struct Events {
+    queues: Mutex<HashMap<String, QueueEvents>>,
+    history: usize,
+    // Milliseconds epoch the server started at, which every event id starts with
+    started: i64,
+}
+
+impl Events {
+    pub fn new(history: usize) -> Events {
+        Events {
+            queues: Mutex::new(HashMap::new()),
+            history: history.max(1),
+            started: Utc::now().timestamp_millis(),
+        }
+    }
+
+    // Records an event on a queue and sends it to every subscriber
+    pub fn publish(&self, queue: &str, kind: EventKind, count: usize, mut ids: Vec<String>) {
+        if count == 0 {
+            return;
+        }
+        ids.truncate(MAX_EVENT_IDS);
+        let mut queues = self.queues.lock().unwrap();
+        let events = self.queue_events(&mut queues, queue);
+        let event = QueueEvent {
+            id: events.next_id,
+            kind,
+            queue: queue.to_string(),
+            count,
+            ids,
+            epoch: Utc::now().timestamp(),
+        };
+        events.next_id += 1;
+        if events.history.len() >= self.history {
+            events.history.pop_front();
+        }
+        events.history.push_back(event.clone());
+        // Fails only when nobody is subscribed
+        let _ = events.sender.send(event);
+    }
+
+    // Stream of a queue's events in server-sent event format, starting after `last_event_id`.
+    // The stream ends if the client falls too far behind, so it can reconnect and resume
+    pub fn subscribe(
+        self: &Arc<Self>,
+        queue: &str,
+        last_event_id: Option<&str>,
+    ) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+        let started = self.started;
+        let mut queues = self.queues.lock().unwrap();
+        let events = self.queue_events(&mut queues, queue);
+        // Subscribed under the same lock the history is read with, so no event is missed or repeated
+        let receiver = events.sender.subscribe();
+        let replay: Vec<Bytes> = match last_event_id.map(|id| self.resume_after(id)) {
+            Some(last) => events
+                .history
+                .iter()
+                .filter(|event| event.id > last)
+                .map(|event| event.to_sse(started))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let subscriber = Subscriber {
+            events: self.clone(),
+            queue: queue.to_string(),
+            receiver: Some(receiver),
+        };
+        let live = stream::unfold(subscriber, move |mut subscriber| async move {
+            let receiver = subscriber.receiver.as_mut()?;
+            match timeout(KEEP_ALIVE_INTERVAL, receiver.recv()).await {
+                Ok(Ok(event)) => Some((event.to_sse(started), subscriber)),
+                Ok(Err(RecvError::Lagged(_))) | Ok(Err(RecvError::Closed)) => None,
+                Err(_) => Some((Bytes::from_static(b": keep-alive\n\n"), subscriber)),
+            }
+        });
+        stream::iter(replay).chain(live).map(Ok)
+    }
+
+    // Number of the last event a client resuming with `Last-Event-ID` received. Ids from before a
+    // restart, or that cannot be read, resume from the start of the history
+    fn resume_after(&self, last_event_id: &str) -> u64 {
+        match last_event_id.split_once('-') {
+            Some((started, id)) if started.parse() == Ok(self.started) => id.parse().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    fn queue_events<'a>(
+        &self,
+        queues: &'a mut HashMap<String, QueueEvents>,
+        queue: &str,
+    ) -> &'a mut QueueEvents {
+        queues
+            .entry(queue.to_string())
+            .or_insert_with(|| QueueEvents {
+                next_id: 1,
+                history: VecDeque::new(),
+                sender: broadcast::channel(self.history).0,
+            })
+    }
+}
+
+// Subscription to a queue's live events. Once it ends, the queue's entry is removed if nothing else
+// is subscribed and it has no history, so streams of queues that never see activity leave nothing behind
+struct Subscriber {
+    events: Arc<Events>,
+    queue: String,
+    receiver: Option<broadcast::Receiver<QueueEvent>>,
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        // Unsubscribed first, then checked under the lock new subscribers take
+        self.receiver.take();
+        let mut queues = self.events.queues.lock().unwrap();
+        let unused = queues
+            .get(&self.queue)
+            .is_some_and(|events| events.history.is_empty() && events.sender.receiver_count() == 0);
+        if unused {
+            queues.remove(&self.queue);
+        }
+    }
+}
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use crate::libs::{
+    filter::Filter,
+    structs::{BindingConfig, ExchangeConfig, Item},
+};
+
+// Most dot separated words a routing key, or a binding's, can have
+pub const MAX_ROUTING_KEY_WORDS: usize = 32;
+
+// Exchanges declared in `[exchanges.<name>]` config sections. Items published to an exchange are
+// added to the queue of every binding they match, so one request fans out to many queues
+pub struct Exchanges {
+    exchanges: HashMap<String, Vec<Binding>>,
+}
+
+// Binding of an exchange to a queue, matching items by routing key and by a filter over the item
+struct Binding {
+    config: BindingConfig,
+    // Words of `routing_key`, where `*` stands for exactly one word and `#` for any number of them
+    pattern: Option<Vec<String>>,
+    filter: Option<Filter>,
+}
+
+impl Exchanges {
+    // Compiles the bindings of every exchange, failing on the first invalid filter
+    pub fn load(configs: HashMap<String, ExchangeConfig>) -> Result<Exchanges, String> {
+        let mut exchanges = HashMap::new();
+        for (name, config) in configs {
+            let mut bindings = Vec::new();
+            for binding in config.bindings.unwrap_or_default() {
+                let filter = Filter::new(binding.filter.as_deref(), None, None).map_err(|e| {
+                    format!(
+                        "exchange '{}' binding to '{}' has an {}",
+                        name, binding.queue, e
+                    )
+                })?;
+                bindings.push(Binding {
+                    pattern: binding
+                        .routing_key
+                        .as_deref()
+                        .map(|key| words(key).into_iter().map(str::to_string).collect()),
+                    filter,
+                    config: binding,
+                });
+            }
+            exchanges.insert(name, bindings);
+        }
+        Ok(Exchanges { exchanges })
+    }
+
+    pub fn contains(&self, exchange: &str) -> bool {
+        self.exchanges.contains_key(exchange)
+    }
+
+    // Every exchange with its bindings, sorted by name
+    pub fn all(&self) -> Vec<(String, Vec<BindingConfig>)> {
+        let mut exchanges: Vec<(String, Vec<BindingConfig>)> = self
+            .exchanges
+            .iter()
+            .map(|(name, bindings)| {
+                let configs = bindings
+                    .iter()
+                    .map(|binding| binding.config.clone())
+                    .collect();
+                (name.clone(), configs)
+            })
+            .collect();
+        exchanges.sort_by(|a, b| a.0.cmp(&b.0));
+        exchanges
+    }
+
+    // Queues an item published with the routing key is added to, in the order their bindings are declared
+    // and each only once. None if the exchange is not declared
+    pub fn route(
+        &self,
+        exchange: &str,
+        routing_key: Option<&str>,
+        item: &Item,
+    ) -> Option<Vec<String>> {
+        let key = routing_key.map(words).unwrap_or_default();
+        let mut queues: Vec<String> = Vec::new();
+        for binding in self.exchanges.get(exchange)? {
+            if queues.contains(&binding.config.queue) {
+                continue;
+            }
+            let routed = binding
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| matches_key(pattern, &key))
+                && binding
+                    .filter
+                    .as_ref()
+                    .is_none_or(|filter| filter.matches(item));
+            if routed {
+                queues.push(binding.config.queue.clone());
+            }
+        }
+        Some(queues)
+    }
+}
+
+// Words of a routing key, separated by dots. An empty key has none
+pub fn words(key: &str) -> Vec<&str> {
+    match key {
+        "" => Vec::new(),
+        key => key.split('.').collect(),
+    }
+}
+
+// True if the words of a routing key match a binding's pattern. Takes time proportional to the number of
+// pattern words times key words, however many `#` the pattern has
+fn matches_key(pattern: &[String], key: &[&str]) -> bool {
+    // Whether the pattern words so far match the first `n` words of the key, by `n`
+    let mut matched = vec![false; key.len() + 1];
+    matched[0] = true;
+    for word in pattern {
+        if word == "#" {
+            // Any number of words, including none, so every longer prefix matches too
+            for n in 1..=key.len() {
+                matched[n] |= matched[n - 1];
+            }
+        } else {
+            for n in (1..=key.len()).rev() {
+                matched[n] = matched[n - 1] && (word == "*" || word == key[n - 1]);
+            }
+            matched[0] = false;
+        }
+    }
+    matched[key.len()]
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
@@ -0,0 +1,60 @@
+use std::{sync::Arc, time::Duration};
+
+use actix_web::rt;
+use chrono::Utc;
+use log::{error, info};
+
+use crate::libs::{
+    events::{EventKind, Events},
+    storage::Storage,
+    structs::ExpiredTotals,
+};
+
+// Default number of seconds between sweeps for expired items
+pub const DEFAULT_EXPIRY_INTERVAL: u64 = 10;
+
+// Periodically removes expired items from every queue, adding how many went to `totals`
+pub fn spawn_expiry_task(
+    storage: Arc<dyn Storage>,
+    totals: ExpiredTotals,
+    events: Arc<Events>,
+    interval: u64,
+) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(Duration::from_secs(interval.max(1)));
+        loop {
+            interval.tick().await;
+            let expired = match storage.expire(Utc::now().timestamp()) {
+                Ok(expired) => expired,
+                Err(e) => {
+                    error!("Failed to remove expired items: {}", e);
+                    continue;
+                }
+            };
+
+            let mut totals = totals.lock().unwrap();
+            for (queue, count) in expired {
+                info!("Removed {} expired items from queue '{}'", count, queue);
+                events.publish(&queue, EventKind::Expired, count, Vec::new());
+                *totals.entry(queue).or_default() += count as u64;
+            }
+        }
+    });
+}
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
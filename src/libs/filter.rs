@@ -0,0 +1,91 @@
+use jmespath::{Expression, Variable};
+
+use crate::libs::structs::Item;
+
+// Selects the items a preview or fetch returns, by when they were received and by a JMESPath
+// expression evaluated against each item as it is returned, with its `content` and `meta`
+pub struct Filter {
+    expression: Option<Expression<'static>>,
+    // Earliest `received_epoch` selected
+    since: Option<i64>,
+    // `received_epoch` items must be received before to be selected
+    until: Option<i64>,
+}
+
+impl Filter {
+    // Builds a filter from an expression, such as `content.type == 'invoice'`, and a window of
+    // received times. None if it would select every item
+    pub fn new(
+        expression: Option<&str>,
+        since: Option<i64>,
+        until: Option<i64>,
+    ) -> Result<Option<Filter>, String> {
+        if let (Some(since), Some(until)) = (since, until) {
+            if since >= until {
+                return Err("since must be before until".to_string());
+            }
+        }
+        let expression = expression
+            .map(|source| {
+                jmespath::compile(source).map_err(|e| {
+                    // Only the first line, the rest points at the error in the expression
+                    let e = e.to_string();
+                    format!("invalid filter. {}", e.lines().next().unwrap_or_default())
+                })
+            })
+            .transpose()?;
+        if expression.is_none() && since.is_none() && until.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Filter {
+            expression,
+            since,
+            until,
+        }))
+    }
+
+    // True if the item was received within the window, and the expression gives a truthy result
+    // for it. Items the expression cannot be evaluated against do not match
+    pub fn matches(&self, item: &Item) -> bool {
+        if self.since.is_some() || self.until.is_some() {
+            let received = match item.received_epoch() {
+                Some(received) => received,
+                None => return false,
+            };
+            if self.since.is_some_and(|since| received < since)
+                || self.until.is_some_and(|until| received >= until)
+            {
+                return false;
+            }
+        }
+        self.expression.as_ref().is_none_or(|expression| {
+            Variable::from_serializable(item)
+                .ok()
+                .and_then(|item| expression.search(item).ok())
+                .is_some_and(|result| result.is_truthy())
+        })
+    }
+}
+
+// True if there is no filter, or the item matches it
+pub fn selects(filter: Option<&Filter>, item: &Item) -> bool {
+    filter.is_none_or(|filter| filter.matches(item))
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
@@ -10,7 +10,10 @@ use actix_web::{
 };
 use futures_util::Future;
 
-use crate::libs::{structs::AppState, utils::validate_api_key};
+use crate::libs::{
+    structs::AppState,
+    utils::{validate_admin_api_key, validate_api_key},
+};
 
 pub struct Auth;
 
@@ -27,12 +30,39 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AuthMiddleware { service }))
+        ready(Ok(AuthMiddleware {
+            service,
+            admin: false,
+        }))
+    }
+}
+
+// Same as `Auth`, but only allows keys from `admin_api_keys`, for privileged requests
+pub struct AdminAuth;
+
+impl<S, B> Transform<S, ServiceRequest> for AdminAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthMiddleware {
+            service,
+            admin: true,
+        }))
     }
 }
 
 pub struct AuthMiddleware<S> {
     service: S,
+    admin: bool,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddleware<S>
@@ -51,20 +81,28 @@ where
         let app_state = req.app_data::<Data<AppState>>().unwrap();
         let headers = req.headers();
 
-        let mut auth_success = app_state.api_keys.is_empty();
-        if headers.contains_key("Authorization") && !app_state.api_keys.is_empty() {
+        let mut key_valid = false;
+        let mut admin_success = app_state.admin_auth_disabled();
+        if headers.contains_key("Authorization") {
             let key = headers.get("Authorization").unwrap().to_str().unwrap();
-            auth_success = validate_api_key(app_state, &key.to_string());
+            key_valid = validate_api_key(app_state, &key.to_string());
+            admin_success |= validate_admin_api_key(app_state, &key.to_string());
         }
+        let auth_success = app_state.auth_disabled() || key_valid;
 
+        let admin = self.admin;
         let fut = self.service.call(req);
         Box::pin(async move {
-            if auth_success {
-                let res = fut.await?;
-                Ok(res)
-            } else {
+            // Privileged requests without any valid key are unauthorized even when ordinary ones need none
+            if !auth_success || (admin && !admin_success && !key_valid) {
                 let error = actix_web::error::ErrorUnauthorized("Unauthorized");
                 Err(error)
+            } else if admin && !admin_success {
+                let error = actix_web::error::ErrorForbidden("Forbidden");
+                Err(error)
+            } else {
+                let res = fut.await?;
+                Ok(res)
             }
         })
     }
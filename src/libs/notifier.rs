@@ -0,0 +1,85 @@
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::Notify;
+
+// Default longest number of seconds a fetch can wait for items to arrive
+pub const DEFAULT_MAX_WAIT: u64 = 20;
+
+// Wakes requests waiting for items to arrive in a queue. Only queues something is waiting on have a
+// notifier, so requests for queues that never exist do not leave anything behind
+#[derive(Default)]
+pub struct Notifiers {
+    queues: Mutex<HashMap<String, Arc<Notify>>>,
+}
+
+// Registration of a request waiting on a queue, its notifier is removed once no request is waiting
+pub struct Waiter {
+    notifiers: Arc<Notifiers>,
+    queue: String,
+    notify: Arc<Notify>,
+}
+
+impl Notifiers {
+    // Registers a request waiting on a queue, creating its notifier if nothing else is waiting on it
+    pub fn wait(self: &Arc<Self>, queue: &str) -> Waiter {
+        let notify = self
+            .queues
+            .lock()
+            .unwrap()
+            .entry(queue.to_string())
+            .or_default()
+            .clone();
+        Waiter {
+            notifiers: self.clone(),
+            queue: queue.to_string(),
+            notify,
+        }
+    }
+
+    // Wakes every request currently waiting on a queue
+    pub fn notify(&self, queue: &str) {
+        if let Some(notify) = self.queues.lock().unwrap().get(queue) {
+            notify.notify_waiters();
+        }
+    }
+}
+
+impl Deref for Waiter {
+    type Target = Notify;
+
+    fn deref(&self) -> &Notify {
+        &self.notify
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let mut queues = self.notifiers.queues.lock().unwrap();
+        // Held only by the map and this waiter, checked under the lock new waiters take it with
+        if Arc::strong_count(&self.notify) == 2 {
+            queues.remove(&self.queue);
+        }
+    }
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
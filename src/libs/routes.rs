@@ -1,29 +1,435 @@
 use actix_web::{
-    error, get, post,
+    delete, get, http, post, put, rt,
     web::{self},
-    Error, HttpResponse,
+    Error, HttpRequest, HttpResponse,
 };
 use chrono::Utc;
 use futures_util::StreamExt as _;
-use log::debug;
+use log::{debug, error, info};
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    io,
+    time::{Duration, Instant},
+};
+use tokio::{pin, time::timeout};
+use uuid::Uuid;
 
 use crate::libs::{
-    middleware::Auth,
-    structs::{AppState, Item, Meta, WebError, WebHealth},
+    dedup::content_key,
+    events::EventKind,
+    exchanges::{words, MAX_ROUTING_KEY_WORDS},
+    filter::Filter,
+    middleware::{AdminAuth, Auth},
+    structs::{
+        AppState, DelayedQuery, FetchQuery, Item, Meta, OverflowPolicy, PreviewOrder, PreviewQuery,
+        PurgeQuery, QueueConfig, TopicFetchQuery, WebBulkError, WebEntryError, WebError,
+        WebExchange, WebExchangeItem, WebGroupOffset, WebGroupStats, WebHealth, WebItemId,
+        WebItemIds, WebPublished, WebPurge, WebQueueDefinition, WebQueueStats, WebReceipt,
+        WebRedrive, WebRouted, WebRoutedItem, WebTopicItem, WebTopicStats, WebValidationError,
+        WsQuery,
+    },
+    topics::{check_queue_name, topic_queue},
+    utils::validate_queue,
+    websocket,
 };
 
-const MAX_PAYLOAD_SIZE: usize = 262_144; // Max size of 256k
+pub const DEFAULT_MAX_PAYLOAD_SIZE: usize = 262_144; // Max size of 256k
+pub const DEFAULT_MAX_BULK_PAYLOAD_SIZE: usize = 16_777_216; // Max size of 16M
+pub const MAX_DELAY_SECONDS: i64 = 31_536_000; // Items are delivered within a year
+
+// Waiting fetches check their queue at least this often, whether or not they are woken
+const WAIT_RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+// Items looked at per page when finding the oldest items to drop from a full queue
+const DROP_PAGE_SIZE: usize = 100;
+
+fn push_new_item(data: &web::Data<AppState>, payload_item: Item) -> io::Result<()> {
+    let queue = payload_item.queue.clone();
+    let ids = payload_item.id().map(str::to_string).into_iter().collect();
+    data.storage.push(payload_item)?;
+    data.notifiers.notify(&queue);
+    data.events.publish(&queue, EventKind::Added, 1, ids);
+    debug!(
+        "Queue '{}' now holds {} items",
+        queue,
+        data.storage.count(&queue)?
+    );
+    Ok(())
+}
+
+// Adds items from many queues at once, either all of them or none, dropping the items with the ids in `drop`
+// from full queues in the same operation
+fn push_new_items(
+    data: &web::Data<AppState>,
+    items: Vec<Item>,
+    drop: BTreeMap<String, Vec<String>>,
+) -> io::Result<()> {
+    let mut added: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for item in &items {
+        added
+            .entry(item.queue.clone())
+            .or_default()
+            .push(item.id().unwrap_or_default().to_string());
+    }
+    let mut dropped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for item in data.storage.push_all(items, &drop)? {
+        dropped
+            .entry(item.queue.clone())
+            .or_default()
+            .push(item.id().unwrap_or_default().to_string());
+    }
+    for (queue, ids) in dropped {
+        debug!(
+            "Queue '{}' is full, dropped {} oldest items",
+            queue,
+            ids.len()
+        );
+        data.events
+            .publish(&queue, EventKind::Dropped, ids.len(), ids);
+    }
+    for (queue, ids) in added {
+        data.notifiers.notify(&queue);
+        data.events
+            .publish(&queue, EventKind::Added, ids.len(), ids);
+    }
+    Ok(())
+}
+
+// Why new items were not added
+enum Refusal {
+    // The queue would hold more than `max_items`
+    Items(String, usize),
+    // The queue would hold more than `max_bytes`
+    Bytes(String, usize),
+    // The server would hold more than `max_memory_bytes`
+    Memory(usize),
+    Storage(io::Error),
+}
+
+impl Refusal {
+    fn response(self) -> HttpResponse {
+        match self {
+            Refusal::Items(queue, max) => too_many_requests(&format!(
+                "queue '{}' is full, it holds at most {} items",
+                queue, max
+            )),
+            Refusal::Bytes(queue, max) => insufficient_storage(&format!(
+                "queue '{}' is full, it holds at most {} bytes",
+                queue, max
+            )),
+            Refusal::Memory(max) => {
+                insufficient_storage(&format!("server is full, it holds at most {} bytes", max))
+            }
+            Refusal::Storage(e) => storage_error(e),
+        }
+    }
+}
+
+// Refuses new items that would take the items held in memory past `max_memory_bytes`
+fn check_memory(data: &web::Data<AppState>, bytes: usize) -> Result<(), Refusal> {
+    match data.max_memory_bytes {
+        Some(max) if data.storage.memory_bytes() + bytes > max => Err(Refusal::Memory(max)),
+        _ => Ok(()),
+    }
+}
+
+// Makes room in a queue for new items of the given sizes according to its overflow policy, returning how
+// many of them, in order, may be added and the ids of the items to drop as they are. Limits are checked before
+// items are added, so producers adding to the same queue at once can briefly take it past them
+fn make_room(
+    data: &web::Data<AppState>,
+    queue: &str,
+    sizes: &[usize],
+) -> Result<(usize, Vec<String>), Refusal> {
+    let max_items = data.queues.with(queue, |config| config.max_items);
+    let max_bytes = data.queues.with(queue, |config| config.max_bytes);
+    if max_items.is_none() && max_bytes.is_none() {
+        return Ok((sizes.len(), Vec::new()));
+    }
+    let fits = |items: usize, bytes: usize| {
+        max_items.is_none_or(|max| items <= max) && max_bytes.is_none_or(|max| bytes <= max)
+    };
+
+    let usage = data.storage.usage(queue).map_err(Refusal::Storage)?;
+    let items = usage.items + sizes.len();
+    let bytes = usage.bytes + sizes.iter().sum::<usize>();
+    if fits(items, bytes) {
+        return Ok((sizes.len(), Vec::new()));
+    }
+
+    match data.overflow(queue) {
+        OverflowPolicy::Reject => {}
+        OverflowPolicy::DropNew => {
+            // Items are kept until the first that does not fit, that one and every item after it are dropped
+            let (mut items, mut bytes) = (usage.items, usage.bytes);
+            let kept = sizes
+                .iter()
+                .take_while(|size| {
+                    items += 1;
+                    bytes += **size;
+                    fits(items, bytes)
+                })
+                .count();
+            debug!(
+                "Queue '{}' is full, dropped {} new items",
+                queue,
+                sizes.len() - kept
+            );
+            return Ok((kept, Vec::new()));
+        }
+        OverflowPolicy::DropOldest => {
+            // Only items ready for delivery can be dropped, leased and delayed items stay. They are only
+            // chosen here, and dropped as the new items are added
+            let (mut items, mut bytes) = (items, bytes);
+            let mut count = 0;
+            let mut drop = Vec::new();
+            while !fits(items, bytes) {
+                let page = data
+                    .storage
+                    .drop_candidates(queue, count, Some(DROP_PAGE_SIZE))
+                    .map_err(Refusal::Storage)?;
+                if page.is_empty() {
+                    break;
+                }
+                for item in &page {
+                    if fits(items, bytes) {
+                        break;
+                    }
+                    items -= 1;
+                    bytes = bytes.saturating_sub(item.size());
+                    count += 1;
+                    drop.extend(item.id().map(str::to_string));
+                }
+            }
+            if fits(items, bytes) {
+                return Ok((sizes.len(), drop));
+            }
+        }
+    }
+
+    Err(match max_items {
+        Some(max) if items > max => Refusal::Items(queue.to_string(), max),
+        _ => Refusal::Bytes(queue.to_string(), max_bytes.unwrap_or_default()),
+    })
+}
+
+// Reads a request body, or None if it grows past `limit` bytes
+async fn read_payload(
+    payload: &mut web::Payload,
+    limit: usize,
+) -> Result<Option<web::BytesMut>, Error> {
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if (body.len() + chunk.len()) > limit {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Some(body))
+}
+
+fn payload_too_large(limit: usize) -> HttpResponse {
+    HttpResponse::PayloadTooLarge()
+        .content_type("application/json")
+        .json(WebError {
+            timestamp: Utc::now().to_rfc3339(),
+            error: format!("payload exceeds the {} byte limit", limit),
+        })
+}
+
+fn storage_error(e: io::Error) -> HttpResponse {
+    error!("Storage operation failed: {}", e);
+    HttpResponse::InternalServerError()
+        .content_type("application/json")
+        .json(WebError {
+            timestamp: Utc::now().to_rfc3339(),
+            error: "storage operation failed".to_string(),
+        })
+}
+
+fn not_found(error: &str) -> HttpResponse {
+    HttpResponse::NotFound()
+        .content_type("application/json")
+        .json(WebError {
+            timestamp: Utc::now().to_rfc3339(),
+            error: error.to_string(),
+        })
+}
+
+fn too_many_requests(error: &str) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .content_type("application/json")
+        .json(WebError {
+            timestamp: Utc::now().to_rfc3339(),
+            error: error.to_string(),
+        })
+}
+
+fn insufficient_storage(error: &str) -> HttpResponse {
+    HttpResponse::InsufficientStorage()
+        .content_type("application/json")
+        .json(WebError {
+            timestamp: Utc::now().to_rfc3339(),
+            error: error.to_string(),
+        })
+}
+
+fn bad_request(error: &str) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .content_type("application/json")
+        .json(WebError {
+            timestamp: Utc::now().to_rfc3339(),
+            error: error.to_string(),
+        })
+}
+
+// Response to acknowledging or rejecting a lease
+fn settle_response(result: io::Result<bool>) -> HttpResponse {
+    match result {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => not_found("lease does not exist or has timed out"),
+        Err(e) => storage_error(e),
+    }
+}
+
+// Tells event stream subscribers that items were fetched from a queue
+pub fn publish_fetched(data: &web::Data<AppState>, queue: &str, items: &[Item]) {
+    let ids = items
+        .iter()
+        .filter_map(|item| item.id().map(str::to_string))
+        .collect();
+    data.events
+        .publish(queue, EventKind::Fetched, items.len(), ids);
+}
+
+// Statistics of a queue, alongside how many of its items have expired
+fn queue_stats(data: &web::Data<AppState>, queue: &str) -> io::Result<WebQueueStats> {
+    let stats = data.storage.stats(queue)?;
+    let expired = data
+        .expired
+        .lock()
+        .unwrap()
+        .get(queue)
+        .copied()
+        .unwrap_or_default();
+    Ok(WebQueueStats {
+        queue: queue.to_string(),
+        items: stats.ready,
+        in_flight: stats.in_flight,
+        delayed: stats.delayed,
+        bytes: stats.bytes,
+        oldest_received_epoch: stats.oldest_epoch,
+        newest_received_epoch: stats.newest_epoch,
+        expired,
+    })
+}
+
+// Page of items starting at `offset`, out of `total`, with headers for fetching the next page
+fn page_response(items: Vec<Item>, offset: usize, total: usize) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    response.insert_header(("X-Total-Count", total));
+    // Let clients page through the queue when items remain after this page
+    let next_offset = offset + items.len();
+    if !items.is_empty() && next_offset < total {
+        response.insert_header(("X-Next-Offset", next_offset));
+    }
+    response.content_type("application/json").json(items)
+}
+
+// Applies queue settings to a newly received item and assigns its metadata
+fn prepare_item(data: &web::Data<AppState>, mut item: Item, size: usize) -> Result<Item, String> {
+    // Queues with priorities disabled stay in strict insertion order
+    if !data.priorities(&item.queue) {
+        item.priority = None;
+    }
+
+    // Items without a ttl of their own fall back to the queue's default, if any
+    let ttl = item.ttl.take().or_else(|| data.default_ttl(&item.queue));
+    if matches!(ttl, Some(ttl) if ttl <= 0) {
+        return Err("ttl must be greater than 0".to_string());
+    }
+
+    // Items are held back either for a number of seconds or until a time, not both
+    let delay = match (item.delay_seconds.take(), item.deliver_at.take()) {
+        (Some(_), Some(_)) => {
+            return Err("only one of delay_seconds and deliver_at can be set".to_string())
+        }
+        (Some(delay), None) if delay < 0 => {
+            return Err("delay_seconds cannot be negative".to_string())
+        }
+        (Some(delay), None) => Some(delay),
+        (None, Some(deliver_at)) => match deliver_at.checked_sub(Utc::now().timestamp()) {
+            Some(delay) => Some(delay),
+            None => return Err("deliver_at is out of range".to_string()),
+        },
+        (None, None) => None,
+    };
+    if matches!(delay, Some(delay) if delay > MAX_DELAY_SECONDS) {
+        return Err(format!(
+            "items cannot be delayed by more than {} seconds",
+            MAX_DELAY_SECONDS
+        ));
+    }
 
-fn push_new_item(data: web::Data<AppState>, payload_item: Item) {
-    let mut states = data.item_queue.lock().unwrap();
-    // TODO: This needs validation
-    states.push(payload_item);
+    item.meta = Some(generate_metadata(ttl, delay, size)?);
+    Ok(item)
 }
 
-fn generate_metadata() -> Meta {
-    Meta {
-        received_epoch: Utc::now().timestamp(),
+// Deduplication key of an item: the request's `Idempotency-Key`, else its `dedup_id`, else a digest of its
+// content if the queue deduplicates by content. None if the queue's `dedup_window` is 0
+fn dedup_key(
+    data: &web::Data<AppState>,
+    item: &mut Item,
+    idempotency_key: Option<String>,
+) -> Option<String> {
+    let key = idempotency_key.or(item.dedup_id.take());
+    if data.dedup_window(&item.queue) == 0 {
+        return None;
     }
+    key.or_else(|| {
+        data.content_dedup(&item.queue)
+            .then(|| content_key(&item.content))
+    })
+}
+
+// Claims an item's deduplication key, returning the id of the item already added with it if there is one
+fn claim_dedup(data: &web::Data<AppState>, item: &Item, key: Option<&str>) -> Option<String> {
+    data.dedup.claim(
+        &item.queue,
+        key?,
+        item.id().unwrap_or_default(),
+        data.dedup_window(&item.queue),
+    )
+}
+
+fn generate_metadata(ttl: Option<i64>, delay: Option<i64>, size: usize) -> Result<Meta, String> {
+    let received_epoch = Utc::now().timestamp();
+    let expires_epoch = ttl
+        .map(|ttl| {
+            received_epoch
+                .checked_add(ttl)
+                .ok_or_else(|| "ttl is too large".to_string())
+        })
+        .transpose()?;
+    let deliver_epoch = delay
+        .filter(|delay| *delay > 0)
+        .map(|delay| {
+            received_epoch
+                .checked_add(delay)
+                .ok_or_else(|| "delay is too large".to_string())
+        })
+        .transpose()?;
+    Ok(Meta {
+        id: Uuid::new_v4().to_string(),
+        received_epoch,
+        receive_count: 0,
+        dead_letter_source: None,
+        expires_epoch,
+        deliver_epoch,
+        size,
+        offset: None,
+    })
 }
 
 /// Check health of service
@@ -64,12 +470,28 @@ async fn auth() -> Result<HttpResponse, Error> {
 
 /// Add item
 ///
-/// Add item to a target queue
+/// Add item to a target queue, returning the id it was assigned. Items with `delay_seconds` or `deliver_at` set
+/// are held back from fetches and previews until they are due, which must be within a year. An item with the same `Idempotency-Key` header
+/// or `dedup_id` as one added within the queue's `dedup_window` is not added again, and the original's id is returned.
+/// The content of items added to a queue with a `schema` must match it.
+/// With `exchange` set instead of `queue`, the item is published to that exchange by its `routing_key`,
+/// the same as with `POST /exchanges/{exchange}`, and the response lists the queues it was routed to
 #[utoipa::path(
     responses(
-        (status = 204, description = "Successfully added item to queue"),
+        (status = 201, description = "Successfully added item to queue. Published to an exchange, lists the queues it was added to as `WebRouted` does", body = WebItemId),
+        (status = 200, description = "Item repeats one already added, which has the returned id", body = WebItemId),
         (status = 401, description = "Not authorized"),
-        (status = 400, description = "Bad request")
+        (status = 400, description = "Bad request"),
+        (status = 202, description = "Item discarded, its queue is full and set to drop new items, or it matched no binding of its exchange"),
+        (status = 404, description = "Queue is not declared, and `strict_queues` is set, or exchange is not declared", body = WebError),
+        (status = 413, description = "Item larger than its queue's `max_payload_size`, which is 256 KiB unless configured", body = WebError),
+        (status = 422, description = "Item content does not match its queue's schema, with every part that does not", body = WebValidationError),
+        (status = 429, description = "Queue holds `max_items` items", body = WebError),
+        (status = 507, description = "Queue holds `max_bytes` bytes, or the server `max_memory_bytes`", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Key identifying repeats of the item, used instead of its `dedup_id`")
     ),
     security(
         ("api_key" = [])
@@ -78,19 +500,18 @@ async fn auth() -> Result<HttpResponse, Error> {
 #[post("/item", wrap = "Auth")]
 async fn add_item(
     data: web::Data<AppState>,
+    req: HttpRequest,
     mut payload: web::Payload,
 ) -> Result<HttpResponse, Error> {
     debug!("Item create/ingest request received");
 
-    // Convert payload stream into useful object
-    let mut body = web::BytesMut::new();
-    while let Some(chunk) = payload.next().await {
-        let chunk = chunk?;
-        if (body.len() + chunk.len()) > MAX_PAYLOAD_SIZE {
-            return Err(error::ErrorBadRequest("payload overflow"));
-        }
-        body.extend_from_slice(&chunk);
-    }
+    // Convert payload stream into useful object. The target queue is not known until the body is
+    // parsed, so it is read up to the largest limit of any queue and checked against its own after
+    let largest = data.largest_payload_limit();
+    let body = match read_payload(&mut payload, largest).await? {
+        Some(body) => body,
+        None => return Ok(payload_too_large(largest)),
+    };
 
     let mut item = match serde_json::from_slice::<Item>(&body) {
         Ok(n) => n,
@@ -103,24 +524,425 @@ async fn add_item(
                 }));
         }
     };
+    let idempotency_key = req
+        .headers()
+        .get("Idempotency-Key")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    if let Some(exchange) = item.exchange.take() {
+        if !item.queue.is_empty() {
+            return Ok(bad_request("item cannot have both a queue and an exchange"));
+        }
+        let routing_key = item.routing_key.take();
+        item.dedup_id = idempotency_key.or(item.dedup_id);
+        return Ok(publish(
+            &data,
+            &exchange,
+            routing_key.as_deref(),
+            item,
+            body.len(),
+        ));
+    }
+    if item.queue.is_empty() {
+        return Ok(bad_request("item needs a queue or an exchange"));
+    }
+    if let Err(e) = check_queue_name(&item.queue) {
+        return Ok(bad_request(&e));
+    }
+    if !data.accepts(&item.queue) {
+        return Ok(not_found(&format!(
+            "queue '{}' is not declared",
+            item.queue
+        )));
+    }
+    let limit = data.payload_limit(&item.queue);
+    if body.len() > limit {
+        return Ok(payload_too_large(limit));
+    }
+    if let Err(violations) = data.schemas.validate(&item.queue, &item.content) {
+        return Ok(HttpResponse::UnprocessableEntity()
+            .content_type("application/json")
+            .json(WebValidationError {
+                timestamp: Utc::now().to_rfc3339(),
+                error: "content does not match the queue's schema".to_string(),
+                violations,
+            }));
+    }
+    let mut item = match prepare_item(&data, item, body.len()) {
+        Ok(item) => item,
+        Err(e) => return Ok(bad_request(&e)),
+    };
 
-    item.meta = Some(generate_metadata());
-    push_new_item(data, item);
+    // A repeat of an item added within the queue's dedup window is answered with the original's id
+    let key = dedup_key(&data, &mut item, idempotency_key);
+    if let Some(id) = claim_dedup(&data, &item, key.as_deref()) {
+        return Ok(HttpResponse::Ok()
+            .insert_header(("Location", format!("/items/{}/{}", item.queue, id)))
+            .content_type("application/json")
+            .json(WebItemId { id }));
+    }
 
-    Ok(HttpResponse::NoContent().finish())
+    let queue = item.queue.clone();
+    let id = item.id().unwrap_or_default().to_string();
+    let location = format!("/items/{}/{}", queue, id);
+    let refused = match check_memory(&data, item.size())
+        .and_then(|_| make_room(&data, &queue, &[item.size()]))
+    {
+        Ok((0, _)) => Some(HttpResponse::Accepted().finish()),
+        Ok((_, drop)) if drop.is_empty() => push_new_item(&data, item).err().map(storage_error),
+        Ok((_, drop)) => push_new_items(&data, vec![item], BTreeMap::from([(queue.clone(), drop)]))
+            .err()
+            .map(storage_error),
+        Err(refusal) => Some(refusal.response()),
+    };
+    if let Some(response) = refused {
+        // The item was not added, so retrying it must not be taken for a repeat
+        if let Some(key) = key {
+            data.dedup.release(&queue, &key, &id);
+        }
+        return Ok(response);
+    }
+
+    Ok(HttpResponse::Created()
+        .insert_header(("Location", location))
+        .content_type("application/json")
+        .json(WebItemId { id }))
+}
+
+/// Subscribe to queue
+///
+/// Upgrades to a WebSocket that pushes items from a queue as they are added, each as its own text frame.
+/// Items are removed once their frames are written, unless `visibility_timeout` is set. Then each frame is a `Lease` of one item,
+/// which must be acknowledged with an `{"ack": "<receipt>"}` frame before the timeout passes.
+/// With only `prefetch` set, items must be acknowledged with `{"ack": "<id>"}` frames within 30 seconds instead.
+/// No more than `prefetch` frames, 100 unless set, are sent until earlier ones are acknowledged
+#[utoipa::path(
+    responses(
+        (status = 101, description = "Switched to WebSocket"),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Bad request", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue"),
+        WsQuery
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/ws/{queue}", wrap = "Auth")]
+async fn subscribe(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<WsQuery>,
+    req: HttpRequest,
+    body: web::Payload,
+) -> Result<HttpResponse, Error> {
+    debug!("Subscribe request received");
+
+    if let Err(e) = check_queue_name(&path) {
+        return Ok(bad_request(&e));
+    }
+    if matches!(query.visibility_timeout, Some(visibility_timeout) if visibility_timeout <= 0) {
+        return Ok(bad_request("visibility_timeout must be greater than 0"));
+    }
+    if query.prefetch == Some(0) {
+        return Ok(bad_request("prefetch must be greater than 0"));
+    }
+
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    rt::spawn(websocket::subscribe(
+        data,
+        path.into_inner(),
+        query.into_inner(),
+        session,
+        messages,
+    ));
+    Ok(response)
+}
+
+/// Stream queue events
+///
+/// Streams activity on a queue as server-sent events, one whenever items are added, fetched, expired, purged or dropped.
+/// Each event has an id increasing with every event on the queue, so a client reconnecting with a `Last-Event-ID`
+/// header receives any recent events it missed first. Ids start with when the server started, and a client resuming
+/// from before a restart receives every recent event
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Stream of `added`, `fetched`, `expired`, `purged` and `dropped` events", content_type = "text/event-stream"),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Queue name is reserved for a topic", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue"),
+        ("Last-Event-ID" = Option<String>, Header, description = "Id of the last event received, to resume from")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/events/{queue}", wrap = "Auth")]
+async fn stream_events(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    debug!("Event stream request received");
+
+    if let Err(e) = check_queue_name(&path) {
+        return Ok(bad_request(&e));
+    }
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok());
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(data.events.subscribe(&path.into_inner(), last_event_id)))
+}
+
+/// Add items
+///
+/// Add many items, to any number of queues, in one request. The body is either a JSON array of items,
+/// or one item per line when sent as `application/x-ndjson`. Items are only added if every entry is valid,
+/// otherwise the error of each rejected entry is returned and nothing is added. Entries repeating an item added
+/// within their queue's `dedup_window` are not added again
+#[utoipa::path(
+    request_body(content = [Item], description = "JSON array of items, or newline-delimited JSON items"),
+    responses(
+        (status = 201, description = "Successfully added items, ids are in the order items were sent, with the original's id for repeated items. Entries discarded by full queues set to drop new items are listed in `dropped`", body = WebItemIds),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Bad request, with the error of each rejected entry. Entries larger than their queue's `max_payload_size`, or for undeclared queues when `strict_queues` is set, or whose content does not match their queue's schema, are rejected", body = WebBulkError),
+        (status = 413, description = "Request larger than `max_bulk_payload_size`, which is 16 MiB unless configured", body = WebError),
+        (status = 429, description = "A queue would hold more than `max_items` items, nothing was added", body = WebError),
+        (status = 507, description = "A queue would hold more than `max_bytes` bytes, or the server `max_memory_bytes`, nothing was added", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[post("/items", wrap = "Auth")]
+async fn add_items(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, Error> {
+    debug!("Item bulk create/ingest request received");
+
+    let body = match read_payload(&mut payload, data.max_bulk_payload_size).await? {
+        Some(body) => body,
+        None => return Ok(payload_too_large(data.max_bulk_payload_size)),
+    };
+
+    // Every entry alongside its position counting from 1, still as raw json
+    let ndjson = req
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-ndjson"));
+    let entries: Vec<(usize, Result<Item, String>)> = if ndjson {
+        body[..]
+            .split(|byte| *byte == b'\n')
+            .enumerate()
+            .filter(|(_, line)| !line.trim_ascii().is_empty())
+            .map(|(i, line)| (i + 1, parse_entry(&data, line)))
+            .collect()
+    } else {
+        match serde_json::from_slice::<Vec<serde_json::Value>>(&body) {
+            Ok(values) => values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (i + 1, parse_entry(&data, value.to_string().as_bytes())))
+                .collect(),
+            Err(e) => return Ok(bad_request(&format!("failed to parse json. {}", e))),
+        }
+    };
+    if entries.is_empty() {
+        return Ok(bad_request("no items to add"));
+    }
+
+    let mut items = Vec::with_capacity(entries.len());
+    let mut errors = Vec::new();
+    for (entry, item) in entries {
+        match item {
+            Ok(item) => items.push((entry, item)),
+            Err(error) => errors.push(WebEntryError { entry, error }),
+        }
+    }
+    if !errors.is_empty() {
+        return Ok(HttpResponse::BadRequest()
+            .content_type("application/json")
+            .json(WebBulkError {
+                timestamp: Utc::now().to_rfc3339(),
+                errors,
+            }));
+    }
+
+    match add_entries(&data, items) {
+        Ok(ids) => Ok(HttpResponse::Created()
+            .content_type("application/json")
+            .json(ids)),
+        Err(refusal) => Ok(refusal.response()),
+    }
+}
+
+// Adds prepared entries of a request, keyed by their position counting from 1, either all of them or none.
+// Entries repeating an item added within their queue's dedup window are answered with the original's id
+fn add_entries(
+    data: &web::Data<AppState>,
+    mut items: Vec<(usize, Item)>,
+) -> Result<WebItemIds, Refusal> {
+    let mut ids = BTreeMap::new();
+    let mut claims = Vec::new();
+    items.retain_mut(|(entry, item)| {
+        let key = dedup_key(data, item, None);
+        if let Some(id) = claim_dedup(data, item, key.as_deref()) {
+            ids.insert(*entry, id);
+            return false;
+        }
+        claims.extend(key.map(|key| Claim {
+            entry: *entry,
+            queue: item.queue.clone(),
+            key,
+            id: item.id().unwrap_or_default().to_string(),
+        }));
+        true
+    });
+
+    if let Err(refusal) = check_memory(data, items.iter().map(|(_, item)| item.size()).sum()) {
+        release_claims(data, &claims, |_| true);
+        return Err(refusal);
+    }
+    let mut sizes: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (_, item) in &items {
+        sizes
+            .entry(item.queue.clone())
+            .or_default()
+            .push(item.size());
+    }
+    // Items dropped from full queues are only removed as the new ones are added, so a refused request drops nothing
+    let mut room = BTreeMap::new();
+    let mut drop = BTreeMap::new();
+    for (queue, sizes) in sizes {
+        match make_room(data, &queue, &sizes) {
+            Ok((count, ids)) => {
+                if !ids.is_empty() {
+                    drop.insert(queue.clone(), ids);
+                }
+                room.insert(queue, count);
+            }
+            Err(refusal) => {
+                release_claims(data, &claims, |_| true);
+                return Err(refusal);
+            }
+        };
+    }
+
+    let mut dropped = Vec::new();
+    let items = items
+        .into_iter()
+        .filter_map(|(entry, item)| match room.get_mut(&item.queue) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                ids.insert(entry, item.id().unwrap_or_default().to_string());
+                Some(item)
+            }
+            _ => {
+                dropped.push(entry);
+                None
+            }
+        })
+        .collect::<Vec<Item>>();
+    release_claims(data, &claims, |entry| dropped.contains(&entry));
+    if !items.is_empty() {
+        if let Err(e) = push_new_items(data, items, drop) {
+            release_claims(data, &claims, |_| true);
+            return Err(Refusal::Storage(e));
+        }
+    }
+
+    Ok(WebItemIds {
+        ids: ids.into_values().collect(),
+        dropped,
+    })
+}
+
+// Deduplication key of a bulk request entry, claimed before it is added
+struct Claim {
+    entry: usize,
+    queue: String,
+    key: String,
+    id: String,
+}
+
+// Releases the keys claimed for entries that were not added after all, so retrying them is not taken for a repeat
+fn release_claims(data: &web::Data<AppState>, claims: &[Claim], released: impl Fn(usize) -> bool) {
+    for claim in claims.iter().filter(|claim| released(claim.entry)) {
+        data.dedup.release(&claim.queue, &claim.key, &claim.id);
+    }
+}
+
+// Parses and prepares a single entry of a bulk request, which is held to the same size limit as a lone item
+fn parse_entry(data: &web::Data<AppState>, json: &[u8]) -> Result<Item, String> {
+    let item =
+        serde_json::from_slice::<Item>(json).map_err(|e| format!("failed to parse json. {}", e))?;
+    check_entry(data, item, json.len())
+}
+
+// Checks an item is accepted by its queue and prepares it, as a bulk request entry of `size` bytes
+fn check_entry(data: &web::Data<AppState>, item: Item, size: usize) -> Result<Item, String> {
+    if item.exchange.is_some() {
+        return Err(
+            "items are published to an exchange one at a time, with `POST /item`".to_string(),
+        );
+    }
+    if item.queue.is_empty() {
+        return Err("item needs a queue".to_string());
+    }
+    check_queue_name(&item.queue)?;
+    if !data.accepts(&item.queue) {
+        return Err(format!("queue '{}' is not declared", item.queue));
+    }
+    let limit = data.payload_limit(&item.queue);
+    if size > limit {
+        return Err(format!("item exceeds the {} byte payload limit", limit));
+    }
+    data.schemas
+        .validate(&item.queue, &item.content)
+        .map_err(|violations| {
+            let violations: Vec<String> = violations
+                .iter()
+                .map(|v| match v.path.as_str() {
+                    "" => v.message.clone(),
+                    path => format!("{} {}", path, v.message),
+                })
+                .collect();
+            format!(
+                "content does not match the queue's schema. {}",
+                violations.join(", ")
+            )
+        })?;
+    prepare_item(data, item, size)
 }
 
 /// Preview item queue
 ///
-/// Preview items in a queue, without ingesting them
+/// Preview items in a queue, without ingesting them. Large queues can be paged through using `offset` and `limit`,
+/// and narrowed down to the items matching a `filter` or received between `since` and `until`.
+/// Items are in the order they would be fetched in, unless `order` sorts them by when they were received
 #[utoipa::path(
     responses(
         (status = 200, description = "Items currently in queue", body = [Item]),
         (status = 401, description = "Not authorized"),
-        (status = 400, description = "Bad request")
+        (status = 400, description = "Bad request"),
+        (status = 500, description = "Storage error", body = WebError)
     ),
     params(
-        ("queue" = String, Path, description = "Target queue")
+        ("queue" = String, Path, description = "Target queue"),
+        PreviewQuery
     ),
     security(
         ("api_key" = [])
@@ -130,36 +952,125 @@ async fn add_item(
 async fn get_items(
     data: web::Data<AppState>,
     path: web::Path<String>,
+    query: web::Query<PreviewQuery>,
 ) -> Result<HttpResponse, Error> {
     debug!("Item get all request received");
 
+    if let Err(e) = check_queue_name(&path) {
+        return Ok(bad_request(&e));
+    }
     let rs_query = path.into_inner();
+    let offset = query.offset.unwrap_or(0);
+    let filter = match Filter::new(query.filter.as_deref(), query.since, query.until) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(bad_request(&e)),
+    };
 
-    let items = data.item_queue.lock().unwrap();
-    let mut filtered_items: Vec<Item> = Vec::new();
-
-    for item in items.iter() {
-        if item.queue == rs_query {
-            filtered_items.push(item.clone());
+    // Sorting needs every selected item, so the page is cut out of them afterwards
+    if let Some(order) = query.order {
+        let mut items = match data.storage.preview(&rs_query, 0, None, filter.as_ref()) {
+            Ok(items) => items,
+            Err(e) => return Ok(storage_error(e)),
+        };
+        // Items received in the same second keep the order they would be fetched in, or its reverse
+        if order == PreviewOrder::Newest {
+            items.reverse();
+            items.sort_by_key(|item| Reverse(item.received_epoch()));
+        } else {
+            items.sort_by_key(Item::received_epoch);
         }
+        let total = items.len();
+        let page = items
+            .into_iter()
+            .skip(offset)
+            .take(data.batch_limit(query.limit).unwrap_or(usize::MAX))
+            .collect();
+        return Ok(page_response(page, offset, total));
     }
 
-    Ok(HttpResponse::Ok()
-        .content_type("application/json")
-        .json(filtered_items))
+    let (filtered_items, total) = match data
+        .storage
+        .preview(
+            &rs_query,
+            offset,
+            data.batch_limit(query.limit),
+            filter.as_ref(),
+        )
+        .and_then(|items| {
+            let total = match &filter {
+                Some(filter) => data.storage.count_matching(&rs_query, filter)?,
+                None => data.storage.count(&rs_query)?,
+            };
+            Ok((items, total))
+        }) {
+        Ok(page) => page,
+        Err(e) => return Ok(storage_error(e)),
+    };
+
+    Ok(page_response(filtered_items, offset, total))
+}
+
+/// Preview delayed items
+///
+/// Preview items in a queue that are not yet due for delivery, soonest first. Paged the same way as previewing the queue
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Delayed items in queue", body = [Item]),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Bad request"),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue"),
+        DelayedQuery
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/items/delayed/{queue}", wrap = "Auth")]
+async fn get_delayed_items(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<DelayedQuery>,
+) -> Result<HttpResponse, Error> {
+    debug!("Item get delayed request received");
+
+    if let Err(e) = check_queue_name(&path) {
+        return Ok(bad_request(&e));
+    }
+    let queue = path.into_inner();
+    let offset = query.offset.unwrap_or(0);
+
+    let (items, total) = match data
+        .storage
+        .delayed(&queue, offset, data.batch_limit(query.limit))
+        .and_then(|items| Ok((items, data.storage.count_delayed(&queue)?)))
+    {
+        Ok(page) => page,
+        Err(e) => return Ok(storage_error(e)),
+    };
+
+    Ok(page_response(items, offset, total))
 }
 
 /// Fetch item queue
 ///
-/// Fetch items from a queue. This will ingest them
+/// Fetch items from a queue, oldest first. This will ingest them, unless `visibility_timeout` is set.
+/// Then items are leased instead, and must be acknowledged with the returned receipt before the timeout passes.
+/// With `wait` set, requests to an empty queue are held open until items arrive or the wait is over.
+/// With `filter`, `since` or `until` set, only matching items are fetched, and the rest are left for other consumers
 #[utoipa::path(
     responses(
-        (status = 200, description = "Items fetched from queue", body = [Item]),
+        (status = 200, description = "Items fetched from queue. A `Lease` is returned instead when `visibility_timeout` is set", body = [Item]),
+        (status = 204, description = "Queue is empty, or has no items matching `filter`, `since` and `until`, and stayed that way for any `wait`"),
         (status = 401, description = "Not authorized"),
-        (status = 400, description = "Bad request")
+        (status = 400, description = "Bad request", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
     ),
     params(
-        ("queue" = String, Path, description = "Target queue")
+        ("queue" = String, Path, description = "Target queue"),
+        FetchQuery
     ),
     security(
         ("api_key" = [])
@@ -169,36 +1080,1098 @@ async fn get_items(
 async fn fetch_items(
     data: web::Data<AppState>,
     path: web::Path<String>,
+    query: web::Query<FetchQuery>,
 ) -> Result<HttpResponse, Error> {
     debug!("Item fetch request received");
 
+    if let Err(e) = check_queue_name(&path) {
+        return Ok(bad_request(&e));
+    }
     let rs_query = path.into_inner();
+    let limit = data.batch_limit(query.limit);
+    if matches!(query.visibility_timeout, Some(visibility_timeout) if visibility_timeout <= 0) {
+        return Ok(bad_request("visibility_timeout must be greater than 0"));
+    }
+    let filter = match Filter::new(query.filter.as_deref(), query.since, query.until) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(bad_request(&e)),
+    };
 
-    let mut items_cleanup = Vec::<usize>::new();
-    let mut items = data.item_queue.lock().unwrap();
-    let mut return_items: Vec<Item> = Vec::new();
+    let fetch = || {
+        try_fetch(
+            &data,
+            &rs_query,
+            limit,
+            query.visibility_timeout,
+            filter.as_ref(),
+        )
+    };
 
-    // Get items to return and a list of items to cleanup from shared queue
-    for (i, item) in items.iter().enumerate() {
-        if item.queue == rs_query {
-            items_cleanup.push(i);
-            return_items.push(item.clone());
-        }
+    // Fetches that do not wait are answered straight away, without waiting on the queue
+    let wait = query.wait.unwrap_or(0).min(data.max_wait);
+    if wait == 0 {
+        return Ok(match fetch() {
+            Ok(Some(response)) => response,
+            Ok(None) => HttpResponse::NoContent().finish(),
+            Err(e) => storage_error(e),
+        });
     }
 
-    // Reverse state_cleanup list to resolve indexing issues due to shifting indexes
-    items_cleanup.reverse();
-    // If state found, cleanup and respond
-    if !items_cleanup.is_empty() && !return_items.is_empty() {
-        for target in items_cleanup {
-            items.remove(target);
+    let deadline = Instant::now() + Duration::from_secs(wait);
+    let notifier = data.notifiers.wait(&rs_query);
+    loop {
+        let notified = notifier.notified();
+        pin!(notified);
+        // Registered before checking the queue, so an item added in between still wakes this request
+        notified.as_mut().enable();
+
+        match fetch() {
+            Ok(Some(response)) => return Ok(response),
+            Ok(None) => {}
+            Err(e) => return Ok(storage_error(e)),
         }
-        Ok(HttpResponse::Ok()
-            .content_type("application/json")
-            .json(return_items))
-    } else {
-        Ok(HttpResponse::NoContent().finish())
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(HttpResponse::NoContent().finish());
+        }
+        // Items also become available without being added, when delays end or leases time out
+        let _ = timeout(remaining.min(WAIT_RECHECK_INTERVAL), notified).await;
+    }
+}
+
+// Fetches items from a queue once, returning None if there were none
+fn try_fetch(
+    data: &web::Data<AppState>,
+    queue: &str,
+    limit: Option<usize>,
+    visibility_timeout: Option<i64>,
+    filter: Option<&Filter>,
+) -> io::Result<Option<HttpResponse>> {
+    // At-least-once delivery, items stay hidden in the queue until acknowledged
+    if let Some(visibility_timeout) = visibility_timeout {
+        let lease = data.storage.lease(
+            queue,
+            limit,
+            visibility_timeout,
+            data.dead_letter(queue).as_ref(),
+            filter,
+        )?;
+        return Ok(lease.map(|lease| {
+            publish_fetched(data, queue, &lease.items);
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .json(lease)
+        }));
+    }
+
+    let return_items = data.storage.take(queue, limit, filter)?;
+    publish_fetched(data, queue, &return_items);
+
+    // If items found, respond with them
+    if return_items.is_empty() {
+        return Ok(None);
     }
+    Ok(Some(
+        HttpResponse::Ok()
+            .content_type("application/json")
+            .json(return_items),
+    ))
+}
+
+/// Get item
+///
+/// Get a single item by id, without ingesting it. Leased items are included
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Item with the given id", body = Item),
+        (status = 404, description = "Item does not exist", body = WebError),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Queue name is reserved for a topic", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue"),
+        ("id" = String, Path, description = "Item id")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/items/{queue}/{id}", wrap = "Auth")]
+async fn get_item(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    debug!("Item get request received");
+
+    if let Err(e) = check_queue_name(&path.0) {
+        return Ok(bad_request(&e));
+    }
+    let (queue, id) = path.into_inner();
+    match data.storage.get(&queue, &id) {
+        Ok(Some(item)) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(item)),
+        Ok(None) => Ok(not_found("item does not exist")),
+        Err(e) => Ok(storage_error(e)),
+    }
+}
+
+/// Delete item
+///
+/// Remove a single item by id, whether it is ready or leased
+#[utoipa::path(
+    responses(
+        (status = 204, description = "Item removed"),
+        (status = 404, description = "Item does not exist", body = WebError),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Queue name is reserved for a topic", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue"),
+        ("id" = String, Path, description = "Item id")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[delete("/items/{queue}/{id}", wrap = "Auth")]
+async fn delete_item(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    debug!("Item delete request received");
+
+    if let Err(e) = check_queue_name(&path.0) {
+        return Ok(bad_request(&e));
+    }
+    let (queue, id) = path.into_inner();
+    match data.storage.delete(&queue, &id) {
+        Ok(true) => Ok(HttpResponse::NoContent().finish()),
+        Ok(false) => Ok(not_found("item does not exist")),
+        Err(e) => Ok(storage_error(e)),
+    }
+}
+
+/// Acknowledge leased items
+///
+/// Removes items leased by a fetch with `visibility_timeout` from the queue for good
+#[utoipa::path(
+    request_body = WebReceipt,
+    responses(
+        (status = 204, description = "Items acknowledged"),
+        (status = 404, description = "Lease does not exist or has timed out", body = WebError),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Bad request"),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[post("/items/{queue}/ack", wrap = "Auth")]
+async fn ack_items(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<WebReceipt>,
+) -> Result<HttpResponse, Error> {
+    debug!("Item ack request received");
+
+    if let Err(e) = check_queue_name(&path) {
+        return Ok(bad_request(&e));
+    }
+    let rs_query = path.into_inner();
+    Ok(settle_response(data.storage.ack(&rs_query, &body.receipt)))
+}
+
+/// Reject leased items
+///
+/// Returns items leased by a fetch with `visibility_timeout` to the front of the queue immediately
+#[utoipa::path(
+    request_body = WebReceipt,
+    responses(
+        (status = 204, description = "Items returned to queue"),
+        (status = 404, description = "Lease does not exist or has timed out", body = WebError),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Bad request"),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[post("/items/{queue}/nack", wrap = "Auth")]
+async fn nack_items(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<WebReceipt>,
+) -> Result<HttpResponse, Error> {
+    debug!("Item nack request received");
+
+    if let Err(e) = check_queue_name(&path) {
+        return Ok(bad_request(&e));
+    }
+    let rs_query = path.into_inner();
+    let result = data.storage.nack(&rs_query, &body.receipt);
+    if let Ok(true) = result {
+        data.notifiers.notify(&rs_query);
+    }
+    Ok(settle_response(result))
+}
+
+/// Redrive dead-lettered items
+///
+/// Moves every item in a dead-letter queue back to the queue it was dead-lettered from
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Number of items moved back to their source queue", body = WebRedrive),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Queue name is reserved for a topic", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Dead-letter queue")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[post("/items/{queue}/redrive", wrap = "Auth")]
+async fn redrive_items(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    debug!("Item redrive request received");
+
+    if let Err(e) = check_queue_name(&path) {
+        return Ok(bad_request(&e));
+    }
+    let rs_query = path.into_inner();
+    match data.storage.redrive(&rs_query) {
+        Ok(redriven) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(WebRedrive { redriven })),
+        Err(e) => Ok(storage_error(e)),
+    }
+}
+
+/// List queues
+///
+/// Lists every queue holding items or declared, by name, with statistics of each
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Every queue", body = [WebQueueStats]),
+        (status = 401, description = "Not authorized"),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/queues", wrap = "Auth")]
+async fn list_queues(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    debug!("Queue list request received");
+
+    let mut names = match data.storage.queues() {
+        Ok(names) => names,
+        Err(e) => return Ok(storage_error(e)),
+    };
+    names.extend(data.queues.all().into_keys());
+    // Topics are listed by `GET /topics`
+    names.retain(|name| check_queue_name(name).is_ok());
+    names.sort();
+    names.dedup();
+
+    let mut queues = Vec::with_capacity(names.len());
+    for name in names {
+        match queue_stats(&data, &name) {
+            Ok(stats) => queues.push(stats),
+            Err(e) => return Ok(storage_error(e)),
+        }
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(queues))
+}
+
+/// Get queue
+///
+/// Statistics of a single queue: how many items are ready, in flight or delayed, their total size and age,
+/// and how many have expired
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Queue statistics", body = WebQueueStats),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Queue name is reserved for a topic", body = WebError),
+        (status = 404, description = "Queue holds no items and is not declared", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/queues/{queue}", wrap = "Auth")]
+async fn get_queue(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    debug!("Queue get request received");
+
+    if let Err(e) = check_queue_name(&path) {
+        return Ok(bad_request(&e));
+    }
+    let rs_query = path.into_inner();
+    let stats = match queue_stats(&data, &rs_query) {
+        Ok(stats) => stats,
+        Err(e) => return Ok(storage_error(e)),
+    };
+    let empty = stats.items == 0 && stats.in_flight == 0 && stats.delayed == 0;
+    if empty && !data.queues.contains(&rs_query) {
+        return Ok(not_found("queue does not exist"));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(stats))
+}
+
+/// Purge queue
+///
+/// Removes every item in a queue, whether it is ready, leased or delayed, returning how many were removed.
+/// With `before` set, only items received before that epoch are removed. Requires a key from `admin_api_keys`
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Number of items removed", body = WebPurge),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Queue name is reserved for a topic", body = WebError),
+        (status = 403, description = "Key is not allowed privileged requests"),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue"),
+        PurgeQuery
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[delete("/queues/{queue}", wrap = "AdminAuth")]
+async fn purge_queue(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<PurgeQuery>,
+) -> Result<HttpResponse, Error> {
+    debug!("Queue purge request received");
+
+    if let Err(e) = check_queue_name(&path) {
+        return Ok(bad_request(&e));
+    }
+    let rs_query = path.into_inner();
+    let purged = match data.storage.purge(&rs_query, query.before) {
+        Ok(purged) => purged,
+        Err(e) => return Ok(storage_error(e)),
+    };
+    data.events
+        .publish(&rs_query, EventKind::Purged, purged, Vec::new());
+    info!("Purged {} items from queue '{}'", purged, rs_query);
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(WebPurge { purged }))
+}
+
+/// List queue definitions
+///
+/// Lists every declared queue, by name, with its settings
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Every declared queue", body = [WebQueueDefinition]),
+        (status = 401, description = "Not authorized")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/definitions", wrap = "Auth")]
+async fn list_definitions(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    debug!("Definition list request received");
+
+    let definitions: Vec<WebQueueDefinition> = data
+        .queues
+        .all()
+        .into_iter()
+        .map(|(queue, config)| WebQueueDefinition { queue, config })
+        .collect();
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(definitions))
+}
+
+/// Get queue definition
+///
+/// Settings of a declared queue
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Queue settings", body = QueueConfig),
+        (status = 401, description = "Not authorized"),
+        (status = 404, description = "Queue is not declared", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/definitions/{queue}", wrap = "Auth")]
+async fn get_definition(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    debug!("Definition get request received");
+
+    match data.queues.get(&path.into_inner()) {
+        Some(config) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(config)),
+        None => Ok(not_found("queue is not declared")),
+    }
+}
+
+/// Set queue definition
+///
+/// Declares a queue, or replaces the settings of one already declared. Changes are saved to `definitions_file`
+/// and applied over the config on startup. A `schema_file` is read from the config directory. Requires a key from `admin_api_keys`
+#[utoipa::path(
+    request_body = QueueConfig,
+    responses(
+        (status = 201, description = "Queue declared", body = QueueConfig),
+        (status = 200, description = "Queue settings replaced", body = QueueConfig),
+        (status = 400, description = "Inconsistent settings, or a schema that could not be compiled", body = WebError),
+        (status = 401, description = "Not authorized"),
+        (status = 403, description = "Key is not allowed privileged requests"),
+        (status = 500, description = "Definitions could not be saved", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[put("/definitions/{queue}", wrap = "AdminAuth")]
+async fn set_definition(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<QueueConfig>,
+) -> Result<HttpResponse, Error> {
+    debug!("Definition set request received");
+
+    let rs_query = path.into_inner();
+    let config = body.into_inner();
+    let errors = validate_queue(&rs_query, &config);
+    if !errors.is_empty() {
+        return Ok(bad_request(&errors.join(", ")));
+    }
+    let schema = match data.schemas.compile(&config) {
+        Ok(schema) => schema,
+        Err(e) => return Ok(bad_request(&e)),
+    };
+
+    let mut response = match data.queues.set(&rs_query, config.clone()) {
+        Ok(true) => HttpResponse::Created(),
+        Ok(false) => HttpResponse::Ok(),
+        Err(e) => return Ok(storage_error(e)),
+    };
+    data.schemas.set(&rs_query, schema);
+    info!("Queue '{}' definition set", rs_query);
+    Ok(response.content_type("application/json").json(config))
+}
+
+/// Delete queue definition
+///
+/// Removes the declaration of a queue, leaving any items in it. Requires a key from `admin_api_keys`
+#[utoipa::path(
+    responses(
+        (status = 204, description = "Queue no longer declared"),
+        (status = 401, description = "Not authorized"),
+        (status = 403, description = "Key is not allowed privileged requests"),
+        (status = 404, description = "Queue is not declared", body = WebError),
+        (status = 500, description = "Definitions could not be saved", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[delete("/definitions/{queue}", wrap = "AdminAuth")]
+async fn delete_definition(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    debug!("Definition delete request received");
+
+    let rs_query = path.into_inner();
+    match data.queues.remove(&rs_query) {
+        Ok(true) => {
+            data.schemas.set(&rs_query, None);
+            info!("Queue '{}' definition deleted", rs_query);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Ok(false) => Ok(not_found("queue is not declared")),
+        Err(e) => Ok(storage_error(e)),
+    }
+}
+
+/// Get queue schema
+///
+/// Returns the JSON Schema the content of items added to a queue must match, as read from its `schema_file`
+/// when it has one rather than an inline `schema`
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Schema of the queue", body = Object),
+        (status = 401, description = "Not authorized"),
+        (status = 404, description = "Queue has no schema", body = WebError)
+    ),
+    params(
+        ("queue" = String, Path, description = "Target queue")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/schemas/{queue}", wrap = "Auth")]
+async fn get_schema(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    debug!("Schema get request received");
+
+    match data.schemas.get(&path.into_inner()) {
+        Some(schema) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(schema)),
+        None => Ok(not_found("queue has no schema")),
+    }
+}
+
+// Statistics of a topic and each of its consumer groups, None if the topic is not declared
+fn topic_stats(data: &web::Data<AppState>, topic: &str) -> io::Result<Option<WebTopicStats>> {
+    let offsets = match data.topics.offsets(topic)? {
+        Some(offsets) => offsets,
+        None => return Ok(None),
+    };
+    Ok(Some(WebTopicStats {
+        topic: topic.to_string(),
+        items: offsets.stats.ready,
+        bytes: offsets.stats.bytes,
+        first_offset: offsets.first,
+        next_offset: offsets.next,
+        oldest_received_epoch: offsets.stats.oldest_epoch,
+        newest_received_epoch: offsets.stats.newest_epoch,
+        retention: offsets.retention,
+        groups: offsets
+            .groups
+            .into_iter()
+            .map(|(group, offset)| WebGroupStats {
+                group,
+                offset,
+                lag: offsets.next.saturating_sub(offset),
+            })
+            .collect(),
+    }))
+}
+
+/// List topics
+///
+/// Lists every topic, by name, with where each of its consumer groups is and how far behind they are
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Every topic", body = [WebTopicStats]),
+        (status = 401, description = "Not authorized"),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/topics", wrap = "Auth")]
+async fn list_topics(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    debug!("Topic list request received");
+
+    let mut topics = Vec::new();
+    for name in data.topics.names() {
+        match topic_stats(&data, &name) {
+            Ok(stats) => topics.extend(stats),
+            Err(e) => return Ok(storage_error(e)),
+        }
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(topics))
+}
+
+/// Get topic
+///
+/// Statistics of a single topic: the items it holds and their offsets, and where each consumer group is
+/// with how many items it has left to read as its `lag`
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Topic statistics", body = WebTopicStats),
+        (status = 401, description = "Not authorized"),
+        (status = 404, description = "Topic is not declared", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("topic" = String, Path, description = "Target topic")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/topics/{topic}", wrap = "Auth")]
+async fn get_topic(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    debug!("Topic get request received");
+
+    match topic_stats(&data, &path.into_inner()) {
+        Ok(Some(stats)) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .json(stats)),
+        Ok(None) => Ok(not_found("topic is not declared")),
+        Err(e) => Ok(storage_error(e)),
+    }
+}
+
+/// Publish item
+///
+/// Appends an item to a topic for every consumer group to read, returning its id and the offset it was given.
+/// Offsets count up from 0 in the order items are published, and are recorded in each item's `meta.offset`
+#[utoipa::path(
+    request_body = WebTopicItem,
+    responses(
+        (status = 201, description = "Item published", body = WebPublished),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Bad request", body = WebError),
+        (status = 404, description = "Topic is not declared", body = WebError),
+        (status = 413, description = "Item larger than `max_payload_size`, which is 256 KiB unless configured", body = WebError),
+        (status = 507, description = "Server holds `max_memory_bytes` bytes", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("topic" = String, Path, description = "Target topic")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[post("/topics/{topic}", wrap = "Auth")]
+async fn publish_item(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, Error> {
+    debug!("Topic publish request received");
+
+    let rs_query = path.into_inner();
+    if !data.topics.contains(&rs_query) {
+        return Ok(not_found("topic is not declared"));
+    }
+    let body = match read_payload(&mut payload, data.max_payload_size).await? {
+        Some(body) => body,
+        None => return Ok(payload_too_large(data.max_payload_size)),
+    };
+    let published = match serde_json::from_slice::<WebTopicItem>(&body) {
+        Ok(n) => n,
+        Err(e) => return Ok(bad_request(&format!("failed to parse json. {}", e))),
+    };
+    if let Err(refusal) = check_memory(&data, body.len()) {
+        return Ok(refusal.response());
+    }
+
+    let queue = topic_queue(&rs_query);
+    let meta = match generate_metadata(None, None, body.len()) {
+        Ok(meta) => meta,
+        Err(e) => return Ok(bad_request(&e)),
+    };
+    let id = meta.id.clone();
+    let item = Item {
+        queue: queue.clone(),
+        content: published.content,
+        priority: None,
+        ttl: None,
+        delay_seconds: None,
+        deliver_at: None,
+        dedup_id: None,
+        exchange: None,
+        routing_key: None,
+        meta: Some(meta),
+    };
+    match data.topics.publish(&rs_query, item) {
+        Ok(Some(offset)) => {
+            data.notifiers.notify(&queue);
+            Ok(HttpResponse::Created()
+                .content_type("application/json")
+                .json(WebPublished { id, offset }))
+        }
+        Ok(None) => Ok(not_found("topic is not declared")),
+        Err(e) => Ok(storage_error(e)),
+    }
+}
+
+/// Read topic
+///
+/// Reads the items of a topic a consumer group has not read yet, oldest first, and moves the group past them.
+/// Every group reads every item, and items are removed once every group has read them or the topic's `retention`
+/// passes. With `wait` set, requests are held open until items are published or the wait is over
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Items read by the group", body = [Item]),
+        (status = 204, description = "Group has read every item, and no more were published during any `wait`"),
+        (status = 401, description = "Not authorized"),
+        (status = 404, description = "Topic is not declared, or the group is not registered", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("topic" = String, Path, description = "Target topic"),
+        ("group" = String, Path, description = "Consumer group"),
+        TopicFetchQuery
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/topics/{topic}/items/{group}", wrap = "Auth")]
+async fn read_topic(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    query: web::Query<TopicFetchQuery>,
+) -> Result<HttpResponse, Error> {
+    debug!("Topic read request received");
+
+    let (topic, group) = path.into_inner();
+    if !data.topics.has_group(&topic, &group) {
+        return Ok(not_found("consumer group is not registered"));
+    }
+    let limit = data.batch_limit(query.limit);
+
+    // None when the group has read every item
+    let read = || match data.topics.read(&topic, &group, limit) {
+        Ok(Some(items)) if !items.is_empty() => Some(
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .json(items),
+        ),
+        Ok(Some(_)) => None,
+        Ok(None) => Some(not_found("consumer group is not registered")),
+        Err(e) => Some(storage_error(e)),
+    };
+
+    // Reads that do not wait are answered straight away, without waiting on the topic
+    let wait = query.wait.unwrap_or(0).min(data.max_wait);
+    if wait == 0 {
+        return Ok(read().unwrap_or_else(|| HttpResponse::NoContent().finish()));
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(wait);
+    let notifier = data.notifiers.wait(&topic_queue(&topic));
+    loop {
+        let notified = notifier.notified();
+        pin!(notified);
+        // Registered before reading, so an item published in between still wakes this request
+        notified.as_mut().enable();
+
+        if let Some(response) = read() {
+            return Ok(response);
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(HttpResponse::NoContent().finish());
+        }
+        let _ = timeout(remaining.min(WAIT_RECHECK_INTERVAL), notified).await;
+    }
+}
+
+/// Set consumer group
+///
+/// Registers a consumer group on a topic, or moves one to read from `offset` on, such as to read items again.
+/// New groups without an `offset` start at the oldest item the topic holds. Offsets are kept within the items
+/// the topic holds. Groups registered this way are saved to `topics_file`. Requires a key from `admin_api_keys`
+#[utoipa::path(
+    request_body = WebGroupOffset,
+    responses(
+        (status = 201, description = "Group registered", body = WebGroupStats),
+        (status = 200, description = "Group moved", body = WebGroupStats),
+        (status = 400, description = "Bad request"),
+        (status = 401, description = "Not authorized"),
+        (status = 403, description = "Key is not allowed privileged requests"),
+        (status = 404, description = "Topic is not declared", body = WebError),
+        (status = 500, description = "Offsets could not be saved", body = WebError)
+    ),
+    params(
+        ("topic" = String, Path, description = "Target topic"),
+        ("group" = String, Path, description = "Consumer group")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[put("/topics/{topic}/groups/{group}", wrap = "AdminAuth")]
+async fn set_group(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+    body: web::Json<WebGroupOffset>,
+) -> Result<HttpResponse, Error> {
+    debug!("Consumer group set request received");
+
+    let (topic, group) = path.into_inner();
+    let (offset, created) = match data.topics.register(&topic, &group, body.offset) {
+        Ok(Some(registered)) => registered,
+        Ok(None) => return Ok(not_found("topic is not declared")),
+        Err(e) => return Ok(storage_error(e)),
+    };
+    let next = match data.topics.offsets(&topic) {
+        Ok(offsets) => offsets.map_or(offset, |offsets| offsets.next),
+        Err(e) => return Ok(storage_error(e)),
+    };
+
+    let mut response = match created {
+        true => {
+            info!("Consumer group '{}' registered on topic '{}'", group, topic);
+            HttpResponse::Created()
+        }
+        false => HttpResponse::Ok(),
+    };
+    Ok(response
+        .content_type("application/json")
+        .json(WebGroupStats {
+            group,
+            offset,
+            lag: next.saturating_sub(offset),
+        }))
+}
+
+/// Delete consumer group
+///
+/// Removes a consumer group from a topic, so items it has not read are no longer kept for it.
+/// Requires a key from `admin_api_keys`
+#[utoipa::path(
+    responses(
+        (status = 204, description = "Group removed"),
+        (status = 401, description = "Not authorized"),
+        (status = 403, description = "Key is not allowed privileged requests"),
+        (status = 404, description = "Topic is not declared, or the group is not registered", body = WebError),
+        (status = 500, description = "Offsets could not be saved", body = WebError)
+    ),
+    params(
+        ("topic" = String, Path, description = "Target topic"),
+        ("group" = String, Path, description = "Consumer group")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[delete("/topics/{topic}/groups/{group}", wrap = "AdminAuth")]
+async fn delete_group(
+    data: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    debug!("Consumer group delete request received");
+
+    let (topic, group) = path.into_inner();
+    match data.topics.unregister(&topic, &group) {
+        Ok(true) => {
+            info!("Consumer group '{}' removed from topic '{}'", group, topic);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Ok(false) => Ok(not_found("consumer group is not registered")),
+        Err(e) => Ok(storage_error(e)),
+    }
+}
+
+/// List exchanges
+///
+/// Lists every exchange, by name, with the bindings deciding which queues its items are added to
+#[utoipa::path(
+    responses(
+        (status = 200, description = "Every exchange", body = [WebExchange]),
+        (status = 401, description = "Not authorized")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[get("/exchanges", wrap = "Auth")]
+async fn list_exchanges(data: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    debug!("Exchange list request received");
+
+    let exchanges: Vec<WebExchange> = data
+        .exchanges
+        .all()
+        .into_iter()
+        .map(|(exchange, bindings)| WebExchange { exchange, bindings })
+        .collect();
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .json(exchanges))
+}
+
+/// Publish item to exchange
+///
+/// Adds an item to the queue of every binding of an exchange it matches, by its `routing_key` and by the
+/// binding's `filter` over the item. Each queue gets its own copy with its own id, and applies its settings
+/// to it as if it were added directly. The item is only added if every queue accepts it, and is discarded
+/// if it matches no binding. Each queue deduplicates the item by the `Idempotency-Key` header or `dedup_id`.
+/// The same as `POST /item` with `exchange` set
+#[utoipa::path(
+    request_body = WebExchangeItem,
+    responses(
+        (status = 201, description = "Item added to the queues it was routed to, with the original's id for queues it repeats an item of. Queues full and set to drop new items are listed in `dropped`", body = WebRouted),
+        (status = 202, description = "Item matched no binding, and was discarded"),
+        (status = 401, description = "Not authorized"),
+        (status = 400, description = "Bad request, a `routing_key` of more than 32 words, or a queue the item was routed to rejected it", body = WebError),
+        (status = 404, description = "Exchange is not declared", body = WebError),
+        (status = 413, description = "Item larger than the `max_payload_size` of every queue", body = WebError),
+        (status = 429, description = "A queue would hold more than `max_items` items, nothing was added", body = WebError),
+        (status = 507, description = "A queue would hold more than `max_bytes` bytes, or the server `max_memory_bytes`, nothing was added", body = WebError),
+        (status = 500, description = "Storage error", body = WebError)
+    ),
+    params(
+        ("exchange" = String, Path, description = "Target exchange"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Key identifying repeats of the item, used instead of its `dedup_id`")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+#[post("/exchanges/{exchange}", wrap = "Auth")]
+async fn publish_to_exchange(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+    req: HttpRequest,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, Error> {
+    debug!("Exchange publish request received");
+
+    let rs_query = path.into_inner();
+    if !data.exchanges.contains(&rs_query) {
+        return Ok(not_found("exchange is not declared"));
+    }
+    // Each queue holds the item to its own limit, checked once it is routed
+    let largest = data.largest_payload_limit();
+    let body = match read_payload(&mut payload, largest).await? {
+        Some(body) => body,
+        None => return Ok(payload_too_large(largest)),
+    };
+    let published = match serde_json::from_slice::<WebExchangeItem>(&body) {
+        Ok(n) => n,
+        Err(e) => return Ok(bad_request(&format!("failed to parse json. {}", e))),
+    };
+
+    let idempotency_key = req
+        .headers()
+        .get("Idempotency-Key")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let item = Item {
+        queue: String::new(),
+        content: published.content,
+        priority: published.priority,
+        ttl: published.ttl,
+        delay_seconds: published.delay_seconds,
+        deliver_at: published.deliver_at,
+        dedup_id: idempotency_key.or(published.dedup_id),
+        exchange: None,
+        routing_key: None,
+        meta: None,
+    };
+    Ok(publish(
+        &data,
+        &rs_query,
+        published.routing_key.as_deref(),
+        item,
+        body.len(),
+    ))
+}
+
+// Adds an item of `size` bytes to the queue of every binding of an exchange it matches, either to all of them
+// or none, listing the queues it was added to
+fn publish(
+    data: &web::Data<AppState>,
+    exchange: &str,
+    routing_key: Option<&str>,
+    item: Item,
+    size: usize,
+) -> HttpResponse {
+    if routing_key.is_some_and(|key| words(key).len() > MAX_ROUTING_KEY_WORDS) {
+        return bad_request(&format!(
+            "routing_key cannot have more than {} words",
+            MAX_ROUTING_KEY_WORDS
+        ));
+    }
+    let queues = match data.exchanges.route(exchange, routing_key, &item) {
+        Some(queues) => queues,
+        None => return not_found("exchange is not declared"),
+    };
+    if queues.is_empty() {
+        debug!(
+            "Item published to exchange '{}' matched no binding",
+            exchange
+        );
+        return HttpResponse::Accepted().finish();
+    }
+
+    let mut items = Vec::with_capacity(queues.len());
+    let mut errors = Vec::new();
+    for (entry, queue) in queues.iter().enumerate() {
+        let copy = Item {
+            queue: queue.clone(),
+            ..item.clone()
+        };
+        match check_entry(data, copy, size) {
+            Ok(copy) => items.push((entry + 1, copy)),
+            Err(error) => errors.push(format!("queue '{}' rejected the item. {}", queue, error)),
+        }
+    }
+    if !errors.is_empty() {
+        return bad_request(&errors.join("; "));
+    }
+
+    let added = match add_entries(data, items) {
+        Ok(added) => added,
+        Err(refusal) => return refusal.response(),
+    };
+    let mut ids = added.ids.into_iter();
+    let mut routed = WebRouted {
+        items: Vec::new(),
+        dropped: Vec::new(),
+    };
+    for (entry, queue) in queues.into_iter().enumerate() {
+        if added.dropped.contains(&(entry + 1)) {
+            routed.dropped.push(queue);
+        } else {
+            let id = ids.next().unwrap_or_default();
+            routed.items.push(WebRoutedItem { queue, id });
+        }
+    }
+    HttpResponse::Created()
+        .content_type("application/json")
+        .json(routed)
 }
 
 /*
@@ -0,0 +1,223 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use actix_web::rt;
+use jsonschema::Validator;
+use log::{info, warn};
+
+use crate::libs::{
+    definitions::QueueDefinitions,
+    structs::{QueueConfig, WebViolation},
+};
+
+// Default number of seconds between checks for changed schema files
+pub const DEFAULT_SCHEMA_RELOAD_INTERVAL: u64 = 5;
+
+// Compiled JSON Schemas of queues, which the content of every item added to them must match
+pub struct Schemas {
+    // Directory `schema_file` paths are relative to
+    dir: PathBuf,
+    queues: RwLock<HashMap<String, Schema>>,
+}
+
+// Schema of a single queue
+pub struct Schema {
+    source: serde_json::Value,
+    validator: Validator,
+    // File the schema was read from and when it was modified, so changes to it are picked up
+    file: Option<(PathBuf, SystemTime)>,
+}
+
+impl Schemas {
+    pub fn new(dir: PathBuf) -> Schemas {
+        Schemas {
+            dir,
+            queues: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // Compiles the schema of every declared queue that has one, with `schema_file` paths relative to `dir`
+    pub fn load(dir: PathBuf, definitions: &QueueDefinitions) -> Result<Schemas, String> {
+        let schemas = Schemas::new(dir);
+        for (queue, config) in definitions.all() {
+            let schema = schemas
+                .compile(&config)
+                .map_err(|e| format!("queue '{}' {}", queue, e))?;
+            schemas.set(&queue, schema);
+        }
+        Ok(schemas)
+    }
+
+    // Compiles the schema set in a queue's config, inline or from its file. None if it has neither
+    pub fn compile(&self, config: &QueueConfig) -> Result<Option<Schema>, String> {
+        let (source, file) = match (&config.schema, &config.schema_file) {
+            (Some(_), Some(_)) => {
+                return Err("only one of schema and schema_file can be set".to_string())
+            }
+            (Some(source), None) => (source.clone(), None),
+            (None, Some(file)) => {
+                let path = self.dir.join(file);
+                let (source, modified) = read_schema(&path)?;
+                (source, Some((path, modified)))
+            }
+            (None, None) => return Ok(None),
+        };
+        let validator =
+            jsonschema::validator_for(&source).map_err(|e| format!("invalid schema. {}", e))?;
+        Ok(Some(Schema {
+            source,
+            validator,
+            file,
+        }))
+    }
+
+    // Sets the schema of a queue, or removes it so any content is accepted
+    pub fn set(&self, queue: &str, schema: Option<Schema>) {
+        let mut queues = self.queues.write().unwrap();
+        match schema {
+            Some(schema) => queues.insert(queue.to_string(), schema),
+            None => queues.remove(queue),
+        };
+    }
+
+    // Returns the schema of a queue as it was written, if it has one
+    pub fn get(&self, queue: &str) -> Option<serde_json::Value> {
+        self.queues
+            .read()
+            .unwrap()
+            .get(queue)
+            .map(|schema| schema.source.clone())
+    }
+
+    // Checks content against the schema of a queue, returning every part that does not match it
+    pub fn validate(
+        &self,
+        queue: &str,
+        content: &serde_json::Value,
+    ) -> Result<(), Vec<WebViolation>> {
+        let queues = self.queues.read().unwrap();
+        let schema = match queues.get(queue) {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+        let violations: Vec<WebViolation> = schema
+            .validator
+            .iter_errors(content)
+            .map(|e| WebViolation {
+                path: e.instance_path().to_string(),
+                message: e.to_string(),
+            })
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    // Recompiles schemas whose file changed since it was read. A file that no longer holds a valid
+    // schema is reported, and the schema compiled before is kept
+    pub fn reload(&self) {
+        let files: Vec<(String, PathBuf, SystemTime)> = self
+            .queues
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(queue, schema)| {
+                let (path, modified) = schema.file.as_ref()?;
+                Some((queue.clone(), path.clone(), *modified))
+            })
+            .collect();
+
+        for (queue, path, modified) in files {
+            if modified_time(&path).is_none_or(|m| m == modified) {
+                continue;
+            }
+            let reloaded = read_schema(&path).and_then(|(source, modified)| {
+                let validator = jsonschema::validator_for(&source)
+                    .map_err(|e| format!("invalid schema. {}", e))?;
+                Ok((source, validator, modified))
+            });
+
+            let mut queues = self.queues.write().unwrap();
+            // The queue's schema may have been replaced while the file was read
+            let schema = match queues.get_mut(&queue) {
+                Some(schema) if schema.file.as_ref().is_some_and(|(p, _)| *p == path) => schema,
+                _ => continue,
+            };
+            match reloaded {
+                Ok((source, validator, modified)) => {
+                    info!(
+                        "Reloaded schema of queue '{}' from '{}'",
+                        queue,
+                        path.display()
+                    );
+                    *schema = Schema {
+                        source,
+                        validator,
+                        file: Some((path, modified)),
+                    };
+                }
+                Err(e) => {
+                    warn!(
+                        "Keeping previous schema of queue '{}', '{}' changed but {}",
+                        queue,
+                        path.display(),
+                        e
+                    );
+                    // Reported once per change, not on every check
+                    if let Some((_, stored)) = schema.file.as_mut() {
+                        *stored = modified_time(&path).unwrap_or(*stored);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Reads a schema file, alongside when it was modified
+fn read_schema(path: &Path) -> Result<(serde_json::Value, SystemTime), String> {
+    let modified = modified_time(path).unwrap_or(SystemTime::UNIX_EPOCH);
+    let raw = fs::read(path).map_err(|e| format!("could not read '{}'. {}", path.display(), e))?;
+    let source = serde_json::from_slice(&raw)
+        .map_err(|e| format!("could not parse '{}'. {}", path.display(), e))?;
+    Ok((source, modified))
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Periodically picks up changes to schema files
+pub fn spawn_reload_task(schemas: Arc<Schemas>, interval: u64) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(Duration::from_secs(interval.max(1)));
+        loop {
+            interval.tick().await;
+            schemas.reload();
+        }
+    });
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
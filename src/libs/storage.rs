@@ -0,0 +1,176 @@
+pub mod disk;
+pub mod memory;
+
+use std::{collections::BTreeMap, io, path::PathBuf, sync::Arc};
+
+use log::info;
+
+use crate::libs::{
+    filter::Filter,
+    storage::{disk::SledStorage, memory::MemoryStorage},
+    structs::{Config, DeadLetter, FsyncPolicy, Item, Lease, StorageBackend},
+};
+
+const DEFAULT_STORAGE_PATH: &str = "./data/conga.db";
+const DEFAULT_WAL_FILE: &str = "./data/conga.wal";
+
+// Backend holding queued items, shared by all web workers
+pub trait Storage: Send + Sync {
+    // Adds an item to its queue, behind any of the same or higher priority
+    fn push(&self, item: Item) -> io::Result<()>;
+    // Adds items across any number of queues at once, either all of them or none. The ready items with the ids
+    // in `drop` are removed from their queue in the same operation, and returned
+    fn push_all(
+        &self,
+        items: Vec<Item>,
+        drop: &BTreeMap<String, Vec<String>>,
+    ) -> io::Result<Vec<Item>>;
+    // Returns up to `limit` items in a queue starting `offset` items from the oldest, without removing them.
+    // With a filter, only matching items are counted and returned
+    fn preview(
+        &self,
+        queue: &str,
+        offset: usize,
+        limit: Option<usize>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Vec<Item>>;
+    // Returns up to `limit` ready items in a queue in the order they are dropped when it is full, starting `offset`
+    // items in. The lowest priority go first, oldest first within each priority
+    fn drop_candidates(
+        &self,
+        queue: &str,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> io::Result<Vec<Item>>;
+    // Removes and returns up to `limit` of the oldest items in a queue, or of those matching the filter
+    fn take(
+        &self,
+        queue: &str,
+        limit: Option<usize>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Vec<Item>>;
+    // Hides up to `limit` of the oldest items in a queue, or of those matching the filter, until they are
+    // acknowledged, or until `visibility_timeout` seconds pass and they are returned to the queue.
+    // Items already delivered the maximum number of times are moved to the dead-letter queue instead
+    fn lease(
+        &self,
+        queue: &str,
+        limit: Option<usize>,
+        visibility_timeout: i64,
+        dead_letter: Option<&DeadLetter>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Option<Lease>>;
+    // Removes leased items for good. False if the lease does not exist or has timed out
+    fn ack(&self, queue: &str, receipt: &str) -> io::Result<bool>;
+    // Returns leased items to the front of the queue. False if the lease does not exist or has timed out
+    fn nack(&self, queue: &str, receipt: &str) -> io::Result<bool>;
+    // Returns an item by id, whether it is ready, leased or delayed
+    fn get(&self, queue: &str, id: &str) -> io::Result<Option<Item>>;
+    // Removes an item by id, whether it is ready, leased or delayed. False if it does not exist
+    fn delete(&self, queue: &str, id: &str) -> io::Result<bool>;
+    // Moves every item in a dead-letter queue back to the queue it came from, returning how many were moved
+    fn redrive(&self, queue: &str) -> io::Result<usize>;
+    // Removes every item, ready, leased or delayed, that expired at or before `now`.
+    // Returns how many were removed from each queue that had expired items
+    fn expire(&self, now: i64) -> io::Result<Vec<(String, usize)>>;
+    // Lists up to `limit` delayed items from `offset`, soonest delivery first
+    fn delayed(&self, queue: &str, offset: usize, limit: Option<usize>) -> io::Result<Vec<Item>>;
+    // Number of delayed items in a queue
+    fn count_delayed(&self, queue: &str) -> io::Result<usize>;
+    // Number of items in a queue ready for delivery
+    fn count(&self, queue: &str) -> io::Result<usize>;
+    // Number of items in a queue ready for delivery that match the filter
+    fn count_matching(&self, queue: &str, filter: &Filter) -> io::Result<usize>;
+    // Removes every item in a queue, ready, leased or delayed, or only those received before `before`.
+    // Returns how many were removed
+    fn purge(&self, queue: &str, before: Option<i64>) -> io::Result<usize>;
+    // Number and total size of every item in a queue, ready, leased or delayed
+    fn usage(&self, queue: &str) -> io::Result<QueueUsage>;
+    // Total size of the items held in memory across every queue
+    fn memory_bytes(&self) -> usize;
+    // Names of every queue holding items, ready, leased or delayed
+    fn queues(&self) -> io::Result<Vec<String>>;
+    // Counts of a queue's items by state, with their size and age
+    fn stats(&self, queue: &str) -> io::Result<QueueStats>;
+}
+
+// How much a queue is holding
+#[derive(Clone, Copy, Default)]
+pub struct QueueUsage {
+    pub items: usize,
+    pub bytes: usize,
+}
+
+// What a queue is holding, by state
+#[derive(Clone, Copy, Default)]
+pub struct QueueStats {
+    pub ready: usize,
+    pub in_flight: usize,
+    pub delayed: usize,
+    pub bytes: usize,
+    pub oldest_epoch: Option<i64>,
+    pub newest_epoch: Option<i64>,
+}
+
+impl QueueStats {
+    // Adds an item's size and age, leaving the caller to count it under its state
+    pub fn record(&mut self, item: &Item) {
+        self.bytes += item.size();
+        if let Some(epoch) = item.received_epoch() {
+            self.oldest_epoch = Some(self.oldest_epoch.map_or(epoch, |oldest| oldest.min(epoch)));
+            self.newest_epoch = Some(self.newest_epoch.map_or(epoch, |newest| newest.max(epoch)));
+        }
+    }
+}
+
+// Opens the storage backend selected in config
+pub fn open_storage(config: &Config) -> io::Result<Arc<dyn Storage>> {
+    match config.storage.unwrap_or(StorageBackend::Memory) {
+        StorageBackend::Memory => {
+            if !config.wal_enabled.unwrap_or(false) {
+                info!("Using in-memory storage");
+                return Ok(Arc::new(MemoryStorage::new()));
+            }
+
+            let wal_file = config
+                .wal_file
+                .clone()
+                .unwrap_or_else(|| DEFAULT_WAL_FILE.to_string());
+            let fsync = config.wal_fsync.unwrap_or(FsyncPolicy::Interval);
+            info!("Using in-memory storage with WAL '{wal_file}', fsync policy is {fsync:?}");
+
+            let storage = Arc::new(MemoryStorage::with_wal(PathBuf::from(wal_file), fsync)?);
+            storage.spawn_wal_tasks(
+                config.wal_fsync_interval.unwrap_or(1),
+                config.wal_compaction_interval.unwrap_or(300),
+            );
+            Ok(storage)
+        }
+        StorageBackend::Sled => {
+            let path = config
+                .storage_path
+                .clone()
+                .unwrap_or_else(|| DEFAULT_STORAGE_PATH.to_string());
+            info!("Using sled storage at '{path}'");
+            Ok(Arc::new(SledStorage::open(&PathBuf::from(path))?))
+        }
+    }
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
@@ -0,0 +1,738 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::Path,
+};
+
+use chrono::Utc;
+use log::info;
+use serde::{Deserialize, Serialize};
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError},
+    Transactional,
+};
+use uuid::Uuid;
+
+use crate::libs::{
+    filter::{selects, Filter},
+    storage::{QueueStats, QueueUsage, Storage},
+    structs::{DeadLetter, Item, Lease},
+};
+
+// Prefix for sled trees holding queues, keeps them apart from sled's own default tree
+const QUEUE_TREE_PREFIX: &str = "queue:";
+// Prefix for sled trees holding a queue's leases, by receipt handle
+const LEASE_TREE_PREFIX: &str = "lease:";
+// Prefix for sled trees holding a queue's delayed items, by delivery time
+const DELAYED_TREE_PREFIX: &str = "delayed:";
+
+// Lease as stored on disk, keeping each item's key so it returns to its original position
+#[derive(Deserialize, Serialize)]
+struct StoredLease {
+    expires_epoch: i64,
+    entries: Vec<(Vec<u8>, Item)>,
+}
+
+// Stores items on disk in an embedded sled database, one tree per queue
+pub struct SledStorage {
+    db: sled::Db,
+}
+
+impl SledStorage {
+    // Opens (or creates) the database at `path`
+    pub fn open(path: &Path) -> io::Result<SledStorage> {
+        let db = sled::open(path)?;
+        info!(
+            "Opened sled storage '{}' with {} queues",
+            path.display(),
+            db.tree_names()
+                .iter()
+                .filter(|name| name.starts_with(QUEUE_TREE_PREFIX.as_bytes()))
+                .count()
+        );
+        Ok(SledStorage { db })
+    }
+
+    fn tree(&self, queue: &str) -> io::Result<sled::Tree> {
+        Ok(self.db.open_tree(format!("{QUEUE_TREE_PREFIX}{queue}"))?)
+    }
+
+    fn lease_tree(&self, queue: &str) -> io::Result<sled::Tree> {
+        Ok(self.db.open_tree(format!("{LEASE_TREE_PREFIX}{queue}"))?)
+    }
+
+    fn delayed_tree(&self, queue: &str) -> io::Result<sled::Tree> {
+        Ok(self.db.open_tree(format!("{DELAYED_TREE_PREFIX}{queue}"))?)
+    }
+
+    // Removes a lease, putting its items back under their original keys if `release` is set.
+    // Returns false if the lease no longer exists
+    fn settle(&self, queue: &str, receipt: &str, release: bool) -> io::Result<bool> {
+        let tree = self.tree(queue)?;
+        let leases = self.lease_tree(queue)?;
+        (&tree, &leases)
+            .transaction(|(tree, leases)| {
+                let value = match leases.remove(receipt.as_bytes())? {
+                    Some(value) => value,
+                    None => return Ok(false),
+                };
+                if release {
+                    let lease: StoredLease =
+                        serde_json::from_slice(&value).map_err(|e| abort(e.into()))?;
+                    for (key, item) in lease.entries {
+                        let value = serde_json::to_vec(&item).map_err(|e| abort(e.into()))?;
+                        tree.insert(key, value)?;
+                    }
+                }
+                Ok(true)
+            })
+            .map_err(tx_error)
+    }
+
+    // Returns the items of every lease whose visibility timeout has passed to the queue, and moves
+    // any delayed items that are due onto the end of it
+    fn refresh(&self, queue: &str) -> io::Result<()> {
+        let now = Utc::now().timestamp();
+        for entry in self.lease_tree(queue)?.iter() {
+            let (receipt, value) = entry?;
+            let lease: StoredLease = serde_json::from_slice(&value)?;
+            if lease.expires_epoch <= now {
+                self.settle(queue, &String::from_utf8_lossy(&receipt), true)?;
+            }
+        }
+
+        let tree = self.tree(queue)?;
+        let delayed = self.delayed_tree(queue)?;
+        for key in delayed.iter().keys() {
+            let key = key?;
+            if delivery_time(&key) > now {
+                break;
+            }
+            (&delayed, &tree)
+                .transaction(|(delayed, tree)| {
+                    if let Some(value) = delayed.remove(&key)? {
+                        let item = decode(&value).map_err(abort)?;
+                        tree.insert(queue_key(&item, tree.generate_id()?), value)?;
+                    }
+                    Ok(())
+                })
+                .map_err(tx_error)?;
+        }
+        Ok(())
+    }
+
+    // Removes up to `limit` of the oldest items in a queue, or of those matching the filter. Items are
+    // only returned if this call removed them, so concurrent takes never hand out the same item twice
+    fn remove_front(
+        &self,
+        queue: &str,
+        limit: Option<usize>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Vec<Item>> {
+        self.refresh(queue)?;
+        let tree = self.tree(queue)?;
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut removed = Vec::new();
+        for entry in tree.iter() {
+            if removed.len() >= limit {
+                break;
+            }
+            let (key, value) = entry?;
+            if filter.is_some_and(|filter| decode(&value).is_ok_and(|item| !filter.matches(&item)))
+            {
+                continue;
+            }
+            if let Some(value) = tree.remove(key)? {
+                removed.push(decode(&value)?);
+            }
+        }
+        Ok(removed)
+    }
+
+    // Removes every item in a queue, ready, leased or delayed, matching `remove`, returning how many were removed
+    fn remove_where(&self, queue: &str, remove: impl Fn(&Item) -> bool) -> io::Result<usize> {
+        let mut removed = 0;
+        for tree in [self.tree(queue)?, self.delayed_tree(queue)?] {
+            for entry in tree.iter() {
+                let (key, value) = entry?;
+                if remove(&decode(&value)?) && tree.remove(key)?.is_some() {
+                    removed += 1;
+                }
+            }
+        }
+
+        let leases = self.lease_tree(queue)?;
+        for receipt in leases.iter().keys() {
+            let receipt = receipt?;
+            // Rewrites the lease without the removed items, or drops it if nothing is left
+            removed += leases
+                .transaction(|leases| {
+                    let value = match leases.get(&receipt)? {
+                        Some(value) => value,
+                        None => return Ok(0),
+                    };
+                    let mut lease: StoredLease =
+                        serde_json::from_slice(&value).map_err(|e| abort(e.into()))?;
+                    let before = lease.entries.len();
+                    lease.entries.retain(|(_, item)| !remove(item));
+                    let removed = before - lease.entries.len();
+                    if lease.entries.is_empty() {
+                        leases.remove(&receipt)?;
+                    } else if removed > 0 {
+                        let value = serde_json::to_vec(&lease).map_err(|e| abort(e.into()))?;
+                        leases.insert(&receipt, value)?;
+                    }
+                    Ok(removed)
+                })
+                .map_err(tx_error)?;
+        }
+        Ok(removed)
+    }
+}
+
+impl Storage for SledStorage {
+    fn push(&self, item: Item) -> io::Result<()> {
+        // Delayed items already due go first, so they stay ahead of this one
+        self.refresh(&item.queue)?;
+        let tree = self.tree(&item.queue)?;
+        let id = self.db.generate_id()?;
+        if item.is_delayed(Utc::now().timestamp()) {
+            self.delayed_tree(&item.queue)?
+                .insert(delayed_key(&item, id), serde_json::to_vec(&item)?)?;
+        } else {
+            tree.insert(queue_key(&item, id), serde_json::to_vec(&item)?)?;
+        }
+        Ok(())
+    }
+
+    fn push_all(
+        &self,
+        items: Vec<Item>,
+        drop: &BTreeMap<String, Vec<String>>,
+    ) -> io::Result<Vec<Item>> {
+        let now = Utc::now().timestamp();
+        // Every queue's tree and delayed tree, so one transaction can write to all of them
+        let mut queues: Vec<&str> = items
+            .iter()
+            .map(|item| item.queue.as_str())
+            .chain(drop.keys().map(String::as_str))
+            .collect();
+        queues.sort_unstable();
+        queues.dedup();
+        let mut trees = Vec::with_capacity(queues.len() * 2);
+        for queue in &queues {
+            self.refresh(queue)?;
+            trees.push(self.tree(queue)?);
+            trees.push(self.delayed_tree(queue)?);
+        }
+        let entries = items
+            .iter()
+            .map(|item| {
+                let index = queues.binary_search(&item.queue.as_str()).unwrap() * 2;
+                Ok((index, item, serde_json::to_vec(item)?))
+            })
+            .collect::<io::Result<Vec<(usize, &Item, Vec<u8>)>>>()?;
+        // Keys of the items to drop, any taken in the meantime are skipped when removing them
+        let mut drops = Vec::new();
+        for (queue, ids) in drop {
+            let index = queues.binary_search(&queue.as_str()).unwrap() * 2;
+            let ids: BTreeSet<&str> = ids.iter().map(String::as_str).collect();
+            for entry in trees[index].iter() {
+                let (key, value) = entry?;
+                if decode(&value)?.id().is_some_and(|id| ids.contains(id)) {
+                    drops.push((index, key));
+                }
+            }
+        }
+
+        trees
+            .as_slice()
+            .transaction(|trees| {
+                let mut dropped = Vec::new();
+                for (index, key) in &drops {
+                    if let Some(value) = trees[*index].remove(key)? {
+                        dropped.push(decode(&value).map_err(abort)?);
+                    }
+                }
+                for (index, item, value) in &entries {
+                    let id = trees[*index].generate_id()?;
+                    if item.is_delayed(now) {
+                        trees[index + 1].insert(delayed_key(item, id), value.as_slice())?;
+                    } else {
+                        trees[*index].insert(queue_key(item, id), value.as_slice())?;
+                    }
+                }
+                Ok(dropped)
+            })
+            .map_err(tx_error)
+    }
+
+    fn preview(
+        &self,
+        queue: &str,
+        offset: usize,
+        limit: Option<usize>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Vec<Item>> {
+        self.refresh(queue)?;
+        let tree = self.tree(queue)?;
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut items = Vec::new();
+        let mut skipped = 0;
+        for value in tree.iter().values() {
+            if items.len() >= limit {
+                break;
+            }
+            let item = decode(&value?)?;
+            if !selects(filter, &item) {
+                continue;
+            }
+            if skipped < offset {
+                skipped += 1;
+                continue;
+            }
+            items.push(item);
+        }
+        Ok(items)
+    }
+
+    fn drop_candidates(
+        &self,
+        queue: &str,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> io::Result<Vec<Item>> {
+        self.refresh(queue)?;
+        let wanted = offset.saturating_add(limit.unwrap_or(usize::MAX));
+        // Read from the lowest priority up, a whole priority at a time until enough are found
+        let mut priorities: Vec<Vec<Item>> = Vec::new();
+        let mut priority = None;
+        let mut found = 0;
+        for entry in self.tree(queue)?.iter().rev() {
+            let (key, value) = entry?;
+            if priority.as_deref() != Some(&key[..8]) {
+                if found >= wanted {
+                    break;
+                }
+                priorities.push(Vec::new());
+                priority = Some(key[..8].to_vec());
+            }
+            priorities.last_mut().unwrap().push(decode(&value)?);
+            found += 1;
+        }
+        let mut items = Vec::with_capacity(found);
+        for mut same in priorities {
+            same.reverse();
+            same.sort_by_key(Item::received_epoch);
+            items.extend(same);
+        }
+        Ok(items
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    fn take(
+        &self,
+        queue: &str,
+        limit: Option<usize>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Vec<Item>> {
+        self.remove_front(queue, limit, filter)
+    }
+
+    fn lease(
+        &self,
+        queue: &str,
+        limit: Option<usize>,
+        visibility_timeout: i64,
+        dead_letter: Option<&DeadLetter>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Option<Lease>> {
+        self.refresh(queue)?;
+        let dead_letter = dead_letter.filter(|policy| policy.queue != queue);
+        let tree = self.tree(queue)?;
+        let leases = self.lease_tree(queue)?;
+        let limit = limit.unwrap_or(usize::MAX);
+
+        // Find the oldest `limit` items to lease, plus any before them due to be dead-lettered
+        let mut keys = Vec::new();
+        let mut alive = 0;
+        for entry in tree.iter() {
+            if alive >= limit {
+                break;
+            }
+            let (key, value) = entry?;
+            let item = decode(&value)?;
+            if !selects(filter, &item) {
+                continue;
+            }
+            let exceeds = dead_letter.is_some_and(|policy| item.exceeds(policy));
+            if !exceeds {
+                alive += 1;
+            }
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            return Ok(None);
+        }
+
+        let mut trees = vec![&tree, &leases];
+        let dlq = match dead_letter {
+            Some(policy) => Some(self.tree(&policy.queue)?),
+            None => None,
+        };
+        if let Some(dlq) = &dlq {
+            trees.push(dlq);
+        }
+
+        let receipt = Uuid::new_v4().to_string();
+        let expires_epoch = Utc::now().timestamp() + visibility_timeout;
+        // Items, lease and dead letters are written together, so a crash can never lose items
+        let lease = trees
+            .as_slice()
+            .transaction(|trees| {
+                let (tree, leases) = (&trees[0], &trees[1]);
+                let mut lease = StoredLease {
+                    expires_epoch,
+                    entries: Vec::with_capacity(keys.len()),
+                };
+                // Items already taken by a concurrent fetch are skipped
+                for key in &keys {
+                    let value = match tree.remove(key)? {
+                        Some(value) => value,
+                        None => continue,
+                    };
+                    let mut item: Item =
+                        serde_json::from_slice(&value).map_err(|e| abort(e.into()))?;
+                    match dead_letter {
+                        Some(policy) if item.exceeds(policy) => {
+                            item.dead_letter(&policy.queue);
+                            let value = serde_json::to_vec(&item).map_err(|e| abort(e.into()))?;
+                            let dlq = &trees[2];
+                            dlq.insert(queue_key(&item, dlq.generate_id()?), value)?;
+                        }
+                        _ => {
+                            item.record_receive();
+                            lease.entries.push((key.to_vec(), item));
+                        }
+                    }
+                }
+                if !lease.entries.is_empty() {
+                    let value = serde_json::to_vec(&lease).map_err(|e| abort(e.into()))?;
+                    leases.insert(receipt.as_bytes(), value)?;
+                }
+                Ok(lease)
+            })
+            .map_err(tx_error)?;
+
+        if lease.entries.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Lease {
+            receipt,
+            expires_epoch,
+            items: lease.entries.into_iter().map(|(_, item)| item).collect(),
+        }))
+    }
+
+    fn ack(&self, queue: &str, receipt: &str) -> io::Result<bool> {
+        self.refresh(queue)?;
+        self.settle(queue, receipt, false)
+    }
+
+    fn nack(&self, queue: &str, receipt: &str) -> io::Result<bool> {
+        self.refresh(queue)?;
+        self.settle(queue, receipt, true)
+    }
+
+    fn get(&self, queue: &str, id: &str) -> io::Result<Option<Item>> {
+        self.refresh(queue)?;
+        if let Some((_, item)) = find(&self.tree(queue)?, id)? {
+            return Ok(Some(item));
+        }
+        for value in self.lease_tree(queue)?.iter().values() {
+            let lease: StoredLease = serde_json::from_slice(&value?)?;
+            if let Some((_, item)) = lease
+                .entries
+                .into_iter()
+                .find(|(_, item)| item.id() == Some(id))
+            {
+                return Ok(Some(item));
+            }
+        }
+        Ok(find(&self.delayed_tree(queue)?, id)?.map(|(_, item)| item))
+    }
+
+    fn delete(&self, queue: &str, id: &str) -> io::Result<bool> {
+        self.refresh(queue)?;
+        for tree in [self.tree(queue)?, self.delayed_tree(queue)?] {
+            if let Some((key, _)) = find(&tree, id)? {
+                return Ok(tree.remove(key)?.is_some());
+            }
+        }
+
+        let leases = self.lease_tree(queue)?;
+        for receipt in leases.iter().keys() {
+            let receipt = receipt?;
+            // Rewrites the lease without the item, or drops it if nothing else is left
+            let deleted = leases
+                .transaction(|leases| {
+                    let value = match leases.get(&receipt)? {
+                        Some(value) => value,
+                        None => return Ok(false),
+                    };
+                    let mut lease: StoredLease =
+                        serde_json::from_slice(&value).map_err(|e| abort(e.into()))?;
+                    let before = lease.entries.len();
+                    lease.entries.retain(|(_, item)| item.id() != Some(id));
+                    if lease.entries.len() == before {
+                        return Ok(false);
+                    }
+                    if lease.entries.is_empty() {
+                        leases.remove(&receipt)?;
+                    } else {
+                        let value = serde_json::to_vec(&lease).map_err(|e| abort(e.into()))?;
+                        leases.insert(&receipt, value)?;
+                    }
+                    Ok(true)
+                })
+                .map_err(tx_error)?;
+            if deleted {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn redrive(&self, queue: &str) -> io::Result<usize> {
+        self.refresh(queue)?;
+        let dlq = self.tree(queue)?;
+        let mut redriven = 0;
+        for entry in dlq.iter() {
+            let (key, value) = entry?;
+            let source = match decode(&value)?.dead_letter_source() {
+                Some(source) if source != queue => source.to_string(),
+                _ => continue,
+            };
+            let target = self.tree(&source)?;
+            // Moved in one transaction, so the item is never in both queues or neither
+            let moved = (&dlq, &target)
+                .transaction(|(dlq, target)| {
+                    let value = match dlq.remove(&key)? {
+                        Some(value) => value,
+                        None => return Ok(false),
+                    };
+                    let mut item: Item =
+                        serde_json::from_slice(&value).map_err(|e| abort(e.into()))?;
+                    item.redrive();
+                    let value = serde_json::to_vec(&item).map_err(|e| abort(e.into()))?;
+                    target.insert(queue_key(&item, target.generate_id()?), value)?;
+                    Ok(true)
+                })
+                .map_err(tx_error)?;
+            if moved {
+                redriven += 1;
+            }
+        }
+        Ok(redriven)
+    }
+
+    fn expire(&self, now: i64) -> io::Result<Vec<(String, usize)>> {
+        let mut expired = Vec::new();
+        for name in self.db.tree_names() {
+            let queue = match name.strip_prefix(QUEUE_TREE_PREFIX.as_bytes()) {
+                Some(queue) => String::from_utf8_lossy(queue).to_string(),
+                None => continue,
+            };
+            let removed = self.remove_where(&queue, |item| item.is_expired(now))?;
+            if removed > 0 {
+                expired.push((queue, removed));
+            }
+        }
+        Ok(expired)
+    }
+
+    fn delayed(&self, queue: &str, offset: usize, limit: Option<usize>) -> io::Result<Vec<Item>> {
+        self.refresh(queue)?;
+        let mut items = Vec::new();
+        for value in self
+            .delayed_tree(queue)?
+            .iter()
+            .values()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+        {
+            items.push(decode(&value?)?);
+        }
+        Ok(items)
+    }
+
+    fn count_delayed(&self, queue: &str) -> io::Result<usize> {
+        self.refresh(queue)?;
+        Ok(self.delayed_tree(queue)?.len())
+    }
+
+    fn count_matching(&self, queue: &str, filter: &Filter) -> io::Result<usize> {
+        self.refresh(queue)?;
+        let mut count = 0;
+        for value in self.tree(queue)?.iter().values() {
+            if filter.matches(&decode(&value?)?) {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn count(&self, queue: &str) -> io::Result<usize> {
+        self.refresh(queue)?;
+        Ok(self.tree(queue)?.len())
+    }
+
+    fn purge(&self, queue: &str, before: Option<i64>) -> io::Result<usize> {
+        self.remove_where(queue, |item| {
+            before.is_none_or(|before| item.received_epoch() < Some(before))
+        })
+    }
+
+    fn usage(&self, queue: &str) -> io::Result<QueueUsage> {
+        self.refresh(queue)?;
+        let mut usage = QueueUsage::default();
+        for tree in [self.tree(queue)?, self.delayed_tree(queue)?] {
+            for value in tree.iter().values() {
+                usage.items += 1;
+                usage.bytes += decode(&value?)?.size();
+            }
+        }
+        for value in self.lease_tree(queue)?.iter().values() {
+            let lease: StoredLease = serde_json::from_slice(&value?)?;
+            usage.items += lease.entries.len();
+            usage.bytes += lease
+                .entries
+                .iter()
+                .map(|(_, item)| item.size())
+                .sum::<usize>();
+        }
+        Ok(usage)
+    }
+
+    // Items are kept on disk, not in memory
+    fn memory_bytes(&self) -> usize {
+        0
+    }
+
+    fn queues(&self) -> io::Result<Vec<String>> {
+        // Every queue has up to three trees, which are left behind empty once it is emptied
+        let names: BTreeSet<String> = self
+            .db
+            .tree_names()
+            .iter()
+            .filter_map(|name| {
+                let name = String::from_utf8_lossy(name);
+                [QUEUE_TREE_PREFIX, LEASE_TREE_PREFIX, DELAYED_TREE_PREFIX]
+                    .iter()
+                    .find_map(|prefix| name.strip_prefix(prefix).map(str::to_string))
+            })
+            .collect();
+        let mut queues = Vec::new();
+        for name in names {
+            if !self.tree(&name)?.is_empty()
+                || !self.lease_tree(&name)?.is_empty()
+                || !self.delayed_tree(&name)?.is_empty()
+            {
+                queues.push(name);
+            }
+        }
+        Ok(queues)
+    }
+
+    fn stats(&self, queue: &str) -> io::Result<QueueStats> {
+        self.refresh(queue)?;
+        let mut stats = QueueStats::default();
+        for value in self.tree(queue)?.iter().values() {
+            stats.record(&decode(&value?)?);
+            stats.ready += 1;
+        }
+        for value in self.delayed_tree(queue)?.iter().values() {
+            stats.record(&decode(&value?)?);
+            stats.delayed += 1;
+        }
+        for value in self.lease_tree(queue)?.iter().values() {
+            let lease: StoredLease = serde_json::from_slice(&value?)?;
+            for (_, item) in &lease.entries {
+                stats.record(item);
+                stats.in_flight += 1;
+            }
+        }
+        Ok(stats)
+    }
+}
+
+fn decode(value: &[u8]) -> io::Result<Item> {
+    Ok(serde_json::from_slice(value)?)
+}
+
+// Key of an item in a queue tree. Prefixed with the inverted priority so the highest come first,
+// then the id, which is monotonic so each priority stays in insertion order
+fn queue_key(item: &Item, id: u64) -> Vec<u8> {
+    let priority = !((item.priority() as u64) ^ (1 << 63));
+    let mut key = priority.to_be_bytes().to_vec();
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+// Key of an item in a delayed tree. Prefixed with the delivery time, so the soonest due come first
+fn delayed_key(item: &Item, id: u64) -> Vec<u8> {
+    let mut key = (item.deliver_epoch() as u64).to_be_bytes().to_vec();
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+// Delivery time a delayed item's key is prefixed with
+fn delivery_time(key: &[u8]) -> i64 {
+    let mut epoch = [0; 8];
+    epoch.copy_from_slice(&key[..8]);
+    u64::from_be_bytes(epoch) as i64
+}
+
+// Finds an item by id, returning its key alongside it
+fn find(tree: &sled::Tree, id: &str) -> io::Result<Option<(sled::IVec, Item)>> {
+    for entry in tree.iter() {
+        let (key, value) = entry?;
+        let item = decode(&value)?;
+        if item.id() == Some(id) {
+            return Ok(Some((key, item)));
+        }
+    }
+    Ok(None)
+}
+
+fn abort(e: io::Error) -> ConflictableTransactionError<io::Error> {
+    ConflictableTransactionError::Abort(e)
+}
+
+fn tx_error(e: TransactionError<io::Error>) -> io::Error {
+    match e {
+        TransactionError::Abort(e) => e,
+        TransactionError::Storage(e) => e.into(),
+    }
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
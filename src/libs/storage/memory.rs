@@ -0,0 +1,950 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard, RwLock},
+    time::Duration,
+};
+
+use actix_web::rt;
+use chrono::Utc;
+use log::{debug, error};
+use uuid::Uuid;
+
+use crate::libs::{
+    filter::{selects, Filter},
+    storage::{QueueStats, QueueUsage, Storage},
+    structs::{DeadLetter, FsyncPolicy, Item, Lease},
+    wal::{Wal, WalRecord},
+};
+
+// Items of a single queue
+#[derive(Default)]
+pub struct QueueState {
+    // Items ready for delivery, highest priority first then oldest first
+    pub items: VecDeque<Item>,
+    // Items delivered but not yet acknowledged, by receipt handle
+    pub leases: HashMap<String, Lease>,
+    // Items held back until their delivery time, soonest first
+    pub delayed: Vec<Item>,
+    // Total size of every item, ready, leased or delayed
+    bytes: usize,
+}
+
+impl QueueState {
+    // Adds an item behind every other item of the same or higher priority
+    pub fn push(&mut self, item: Item) {
+        self.bytes += item.size();
+        self.insert(item);
+    }
+
+    // Puts an item already counted towards the queue's size behind every other item of the same
+    // or higher priority
+    fn insert(&mut self, item: Item) {
+        let index = self
+            .items
+            .partition_point(|queued| queued.priority() >= item.priority());
+        self.items.insert(index, item);
+    }
+
+    // Adds an item ahead of every other item of the same or lower priority
+    fn push_front(&mut self, item: Item) {
+        let index = self
+            .items
+            .partition_point(|queued| queued.priority() > item.priority());
+        self.items.insert(index, item);
+    }
+
+    // Moves up to `count` of the oldest items into a new lease, counting their delivery
+    pub fn lease_front(&mut self, count: usize, receipt: String, expires_epoch: i64) -> Lease {
+        let count = count.min(self.items.len());
+        let items = self.items.drain(..count).collect();
+        self.lease_items(items, receipt, expires_epoch)
+    }
+
+    // Moves the ready items with the given ids into a new lease, counting their delivery
+    pub fn lease_ids(&mut self, ids: &[String], receipt: String, expires_epoch: i64) -> Lease {
+        let items = self.take_ids(ids);
+        self.lease_items(items, receipt, expires_epoch)
+    }
+
+    fn lease_items(&mut self, mut items: Vec<Item>, receipt: String, expires_epoch: i64) -> Lease {
+        for item in items.iter_mut() {
+            item.record_receive();
+        }
+        let lease = Lease {
+            receipt: receipt.clone(),
+            expires_epoch,
+            items,
+        };
+        self.leases.insert(receipt, lease.clone());
+        lease
+    }
+
+    // Adds a lease as it was, without counting its items' delivery again
+    pub fn restore_lease(&mut self, lease: Lease) {
+        self.bytes += lease.items.iter().map(Item::size).sum::<usize>();
+        self.leases.insert(lease.receipt.clone(), lease);
+    }
+
+    // Returns a lease's items to the front of their priority, so they are delivered next
+    pub fn release(&mut self, receipt: &str) -> bool {
+        match self.leases.remove(receipt) {
+            Some(lease) => {
+                for item in lease.items.into_iter().rev() {
+                    self.push_front(item);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Holds an item back until its delivery time, after any due at the same time
+    pub fn delay(&mut self, item: Item) {
+        self.bytes += item.size();
+        let index = self
+            .delayed
+            .partition_point(|delayed| delayed.deliver_epoch() <= item.deliver_epoch());
+        self.delayed.insert(index, item);
+    }
+
+    // Moves delayed items due at or before `now` to the back of their priority in the queue
+    pub fn deliver(&mut self, now: i64) {
+        let count = self
+            .delayed
+            .partition_point(|delayed| !delayed.is_delayed(now));
+        for item in self.delayed.drain(..count).collect::<Vec<Item>>() {
+            self.insert(item);
+        }
+    }
+
+    // Returns an item by id, whether it is ready, leased or delayed
+    pub fn get(&self, id: &str) -> Option<&Item> {
+        self.items
+            .iter()
+            .chain(self.leases.values().flat_map(|lease| lease.items.iter()))
+            .chain(self.delayed.iter())
+            .find(|item| item.id() == Some(id))
+    }
+
+    // Removes an item by id, whether it is ready, leased or delayed. Leases left empty are removed too
+    pub fn delete(&mut self, id: &str) -> Option<Item> {
+        let item = self.remove_by_id(id)?;
+        self.bytes -= item.size();
+        Some(item)
+    }
+
+    fn remove_by_id(&mut self, id: &str) -> Option<Item> {
+        if let Some(index) = self.items.iter().position(|item| item.id() == Some(id)) {
+            return self.items.remove(index);
+        }
+        if let Some(index) = self.delayed.iter().position(|item| item.id() == Some(id)) {
+            return Some(self.delayed.remove(index));
+        }
+        let (receipt, index) = self.leases.iter().find_map(|(receipt, lease)| {
+            let index = lease.items.iter().position(|item| item.id() == Some(id))?;
+            Some((receipt.clone(), index))
+        })?;
+        let lease = self.leases.get_mut(&receipt)?;
+        let item = lease.items.remove(index);
+        if lease.items.is_empty() {
+            self.leases.remove(&receipt);
+        }
+        Some(item)
+    }
+
+    // Removes up to `count` of the oldest ready items
+    pub fn remove_front(&mut self, count: usize) -> Vec<Item> {
+        let removed: Vec<Item> = self.items.drain(..count.min(self.items.len())).collect();
+        self.bytes -= removed.iter().map(Item::size).sum::<usize>();
+        removed
+    }
+
+    // Ids of up to `limit` of the oldest ready items matching the filter
+    pub fn select(&self, limit: usize, filter: &Filter) -> Vec<String> {
+        self.items
+            .iter()
+            .filter(|item| filter.matches(item))
+            .filter_map(|item| item.id().map(str::to_string))
+            .take(limit)
+            .collect()
+    }
+
+    // Removes the ready items with the given ids
+    pub fn remove_ids(&mut self, ids: &[String]) -> Vec<Item> {
+        let removed = self.take_ids(ids);
+        self.bytes -= removed.iter().map(Item::size).sum::<usize>();
+        removed
+    }
+
+    // Takes the ready items with the given ids out of the queue, leaving them counted towards its size
+    fn take_ids(&mut self, ids: &[String]) -> Vec<Item> {
+        let ids: HashSet<&str> = ids.iter().map(String::as_str).collect();
+        let (taken, kept): (Vec<Item>, Vec<Item>) = self
+            .items
+            .drain(..)
+            .partition(|item| item.id().is_some_and(|id| ids.contains(id)));
+        self.items = kept.into();
+        taken
+    }
+
+    // Removes a lease and its items for good
+    pub fn ack(&mut self, receipt: &str) -> bool {
+        match self.leases.remove(receipt) {
+            Some(lease) => {
+                self.bytes -= lease.items.iter().map(Item::size).sum::<usize>();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Removes the item at `index`, readdressed to the dead-letter queue `to`
+    pub fn dead_letter_at(&mut self, index: usize, to: &str) -> Option<Item> {
+        let mut item = self.items.remove(index)?;
+        self.bytes -= item.size();
+        item.dead_letter(to);
+        Some(item)
+    }
+
+    // Removes every ready item dead-lettered from `source`, readdressed back to it
+    pub fn take_dead_letters(&mut self, source: &str) -> Vec<Item> {
+        let (mut redriven, kept): (Vec<Item>, Vec<Item>) = self
+            .items
+            .drain(..)
+            .partition(|item| item.dead_letter_source() == Some(source));
+        self.items = kept.into();
+        self.bytes -= redriven.iter().map(Item::size).sum::<usize>();
+        for item in redriven.iter_mut() {
+            item.redrive();
+        }
+        redriven
+    }
+
+    // Removes every item, ready, leased or delayed, that expired at or before `now`. Leases left empty are removed too
+    pub fn expire(&mut self, now: i64) -> usize {
+        self.remove_where(|item| item.is_expired(now))
+    }
+
+    // Removes every item, ready, leased or delayed, received before `before`, or every item if it is not set
+    pub fn purge(&mut self, before: Option<i64>) -> usize {
+        self.remove_where(|item| before.is_none_or(|before| item.received_epoch() < Some(before)))
+    }
+
+    // Removes every item, ready, leased or delayed, matching `remove`, returning how many were removed
+    fn remove_where(&mut self, remove: impl Fn(&Item) -> bool) -> usize {
+        let before = self.len();
+        let mut removed_bytes = 0;
+        let mut keep = |item: &Item| {
+            if remove(item) {
+                removed_bytes += item.size();
+                return false;
+            }
+            true
+        };
+        self.items.retain(&mut keep);
+        self.delayed.retain(&mut keep);
+        for lease in self.leases.values_mut() {
+            lease.items.retain(&mut keep);
+        }
+        self.leases.retain(|_, lease| !lease.items.is_empty());
+        self.bytes -= removed_bytes;
+        before - self.len()
+    }
+
+    // Number of items, ready, leased or delayed
+    pub fn len(&self) -> usize {
+        self.items.len()
+            + self.delayed.len()
+            + self
+                .leases
+                .values()
+                .map(|lease| lease.items.len())
+                .sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Total size of every item, ready, leased or delayed
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    // Receipts of leases whose visibility timeout has passed
+    fn expired_leases(&self, now: i64) -> Vec<String> {
+        self.leases
+            .values()
+            .filter(|lease| lease.expires_epoch <= now)
+            .map(|lease| lease.receipt.clone())
+            .collect()
+    }
+}
+
+type Queue = Arc<Mutex<QueueState>>;
+
+// Stores items in memory, optionally backed by a write-ahead log.
+// Each queue has its own lock, so work on one queue never blocks another
+pub struct MemoryStorage {
+    queues: RwLock<HashMap<String, Queue>>,
+    wal: Option<Mutex<Wal>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage {
+            queues: RwLock::new(HashMap::new()),
+            wal: None,
+        }
+    }
+
+    // Creates storage restored from the write-ahead log at `path`
+    pub fn with_wal(path: PathBuf, fsync: FsyncPolicy) -> io::Result<MemoryStorage> {
+        let (wal, queues) = Wal::open(path, fsync)?;
+        Ok(MemoryStorage {
+            queues: RwLock::new(
+                queues
+                    .into_iter()
+                    .map(|(name, state)| (name, Arc::new(Mutex::new(state))))
+                    .collect(),
+            ),
+            wal: Some(Mutex::new(wal)),
+        })
+    }
+
+    // Returns a queue if it exists, without creating it
+    fn queue(&self, name: &str) -> Option<Queue> {
+        self.queues.read().unwrap().get(name).cloned()
+    }
+
+    // Returns a queue, creating it if it does not exist yet
+    fn queue_or_create(&self, name: &str) -> Queue {
+        if let Some(queue) = self.queue(name) {
+            return queue;
+        }
+        self.queues
+            .write()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .clone()
+    }
+
+    // Locks a queue after returning any leases that have timed out to it, and moving any delayed
+    // items that are due onto the end of it
+    fn lock_queue<'a>(
+        &self,
+        name: &str,
+        queue: &'a Queue,
+    ) -> io::Result<MutexGuard<'a, QueueState>> {
+        let mut state = queue.lock().unwrap();
+        let now = Utc::now().timestamp();
+        for receipt in state.expired_leases(now) {
+            self.log(WalRecord::Release {
+                queue: name.to_string(),
+                receipt: receipt.clone(),
+            })?;
+            state.release(&receipt);
+        }
+        if state
+            .delayed
+            .first()
+            .is_some_and(|item| !item.is_delayed(now))
+        {
+            self.log(WalRecord::Deliver {
+                queue: name.to_string(),
+                now,
+            })?;
+            state.deliver(now);
+        }
+        Ok(state)
+    }
+
+    // Locks two different queues, always in name order so concurrent callers cannot deadlock
+    fn lock_pair<'a>(
+        &self,
+        (a_name, a): (&str, &'a Queue),
+        (b_name, b): (&str, &'a Queue),
+    ) -> io::Result<(MutexGuard<'a, QueueState>, MutexGuard<'a, QueueState>)> {
+        if a_name < b_name {
+            let a = self.lock_queue(a_name, a)?;
+            Ok((a, self.lock_queue(b_name, b)?))
+        } else {
+            let b = self.lock_queue(b_name, b)?;
+            Ok((self.lock_queue(a_name, a)?, b))
+        }
+    }
+
+    // Moves items that have been delivered too many times from the window of `limit` oldest items,
+    // or of those matching the filter, to the dead-letter queue, so the window can be leased
+    fn dead_letter_window(
+        &self,
+        name: &str,
+        state: &mut QueueState,
+        dead: &mut QueueState,
+        limit: usize,
+        policy: &DeadLetter,
+        filter: Option<&Filter>,
+    ) -> io::Result<()> {
+        while let Some(index) = state
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| selects(filter, item))
+            .take(limit)
+            .find(|(_, item)| item.exceeds(policy))
+            .map(|(index, _)| index)
+        {
+            self.log(WalRecord::DeadLetter {
+                queue: name.to_string(),
+                index,
+                to: policy.queue.clone(),
+            })?;
+            if let Some(item) = state.dead_letter_at(index, &policy.queue) {
+                dead.push(item);
+            }
+        }
+        Ok(())
+    }
+
+    // Leases the oldest items of an already locked queue
+    fn lease_locked(
+        &self,
+        name: &str,
+        state: &mut QueueState,
+        limit: usize,
+        visibility_timeout: i64,
+        filter: Option<&Filter>,
+    ) -> io::Result<Option<Lease>> {
+        let ids = filter.map(|filter| state.select(limit, filter));
+        let count = ids.as_ref().map_or(limit.min(state.items.len()), Vec::len);
+        if count == 0 {
+            return Ok(None);
+        }
+
+        let receipt = Uuid::new_v4().to_string();
+        let expires_epoch = Utc::now().timestamp() + visibility_timeout;
+        if let Some(ids) = ids {
+            self.log(WalRecord::LeaseIds {
+                queue: name.to_string(),
+                receipt: receipt.clone(),
+                ids: ids.clone(),
+                expires_epoch,
+            })?;
+            return Ok(Some(state.lease_ids(&ids, receipt, expires_epoch)));
+        }
+        self.log(WalRecord::Lease {
+            queue: name.to_string(),
+            receipt: receipt.clone(),
+            count,
+            expires_epoch,
+        })?;
+        Ok(Some(state.lease_front(count, receipt, expires_epoch)))
+    }
+
+    // Records an operation before it is applied, so it is never acknowledged without being logged.
+    // Callers hold the queue's lock, keeping the log in the same order as each queue
+    fn log(&self, record: WalRecord) -> io::Result<()> {
+        if let Some(wal) = &self.wal {
+            wal.lock().unwrap().append(&record)?;
+        }
+        Ok(())
+    }
+
+    // Removes up to `limit` of the oldest items in a queue, or of those matching the filter,
+    // logging the removal first
+    fn remove_front(
+        &self,
+        name: &str,
+        limit: Option<usize>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Vec<Item>> {
+        let queue = match self.queue(name) {
+            Some(queue) => queue,
+            None => return Ok(Vec::new()),
+        };
+        let mut state = self.lock_queue(name, &queue)?;
+        if let Some(filter) = filter {
+            let ids = state.select(limit.unwrap_or(usize::MAX), filter);
+            if ids.is_empty() {
+                return Ok(Vec::new());
+            }
+            self.log(WalRecord::RemoveIds {
+                queue: name.to_string(),
+                ids: ids.clone(),
+            })?;
+            return Ok(state.remove_ids(&ids));
+        }
+        let count = limit.map_or(state.items.len(), |limit| limit.min(state.items.len()));
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        self.log(WalRecord::Remove {
+            queue: name.to_string(),
+            count,
+        })?;
+        Ok(state.remove_front(count))
+    }
+
+    // Removes a lease if it has not timed out, returning its items to the queue if `release` is set
+    fn settle(&self, name: &str, receipt: &str, release: bool) -> io::Result<bool> {
+        let queue = match self.queue(name) {
+            Some(queue) => queue,
+            None => return Ok(false),
+        };
+        let mut state = self.lock_queue(name, &queue)?;
+        if !state.leases.contains_key(receipt) {
+            return Ok(false);
+        }
+
+        let queue = name.to_string();
+        let receipt = receipt.to_string();
+        if release {
+            self.log(WalRecord::Release {
+                queue,
+                receipt: receipt.clone(),
+            })?;
+            state.release(&receipt);
+        } else {
+            self.log(WalRecord::Ack {
+                queue,
+                receipt: receipt.clone(),
+            })?;
+            state.ack(&receipt);
+        }
+        Ok(true)
+    }
+
+    // Starts the background tasks that sync and compact the write-ahead log
+    pub fn spawn_wal_tasks(self: &Arc<Self>, fsync_interval: u64, compaction_interval: u64) {
+        if self.wal.is_none() {
+            return;
+        }
+
+        let storage = self.clone();
+        rt::spawn(async move {
+            let mut interval = rt::time::interval(Duration::from_secs(fsync_interval.max(1)));
+            loop {
+                interval.tick().await;
+                let wal = storage.wal.as_ref().unwrap();
+                if let Err(e) = wal.lock().unwrap().sync() {
+                    error!("Failed to sync WAL: {}", e);
+                }
+            }
+        });
+
+        let storage = self.clone();
+        rt::spawn(async move {
+            let mut interval = rt::time::interval(Duration::from_secs(compaction_interval.max(1)));
+            // First tick completes immediately, the log was already compacted on open
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                storage.compact_wal();
+            }
+        });
+    }
+
+    // Rewrites the log from a consistent snapshot of every queue
+    fn compact_wal(&self) {
+        let queues = self.queues.read().unwrap();
+        // Every queue is locked in name order before the log, the same order every other operation uses
+        let mut names: Vec<&String> = queues.keys().collect();
+        names.sort();
+        let locked: Vec<(&String, MutexGuard<QueueState>)> = names
+            .into_iter()
+            .map(|name| (name, queues[name].lock().unwrap()))
+            .collect();
+        let count: usize = locked
+            .iter()
+            .map(|(_, state)| state.items.len() + state.leases.len() + state.delayed.len())
+            .sum();
+
+        let wal = self.wal.as_ref().unwrap();
+        match wal
+            .lock()
+            .unwrap()
+            .compact(locked.iter().map(|(name, state)| (name.as_str(), &**state)))
+        {
+            Ok(_) => debug!("Compacted WAL to {} items and leases", count),
+            Err(e) => error!("Failed to compact WAL: {}", e),
+        }
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn push(&self, item: Item) -> io::Result<()> {
+        let queue = self.queue_or_create(&item.queue);
+        let mut state = self.lock_queue(&item.queue, &queue)?;
+        if item.is_delayed(Utc::now().timestamp()) {
+            self.log(WalRecord::Delay { item: item.clone() })?;
+            state.delay(item);
+        } else {
+            self.log(WalRecord::Push { item: item.clone() })?;
+            state.push(item);
+        }
+        Ok(())
+    }
+
+    fn push_all(
+        &self,
+        items: Vec<Item>,
+        drop: &BTreeMap<String, Vec<String>>,
+    ) -> io::Result<Vec<Item>> {
+        let mut by_queue: BTreeMap<String, Vec<Item>> = BTreeMap::new();
+        for item in items {
+            by_queue.entry(item.queue.clone()).or_default().push(item);
+        }
+        for queue in drop.keys() {
+            by_queue.entry(queue.clone()).or_default();
+        }
+        let queues: Vec<(String, Queue)> = by_queue
+            .keys()
+            .map(|name| (name.clone(), self.queue_or_create(name)))
+            .collect();
+        // Locked in name order, the same order every other operation uses
+        let mut locked = Vec::with_capacity(queues.len());
+        for (name, queue) in &queues {
+            locked.push(self.lock_queue(name, queue)?);
+        }
+
+        let now = Utc::now().timestamp();
+        let removals = drop.iter().map(|(queue, ids)| WalRecord::RemoveIds {
+            queue: queue.clone(),
+            ids: ids.clone(),
+        });
+        let records = removals
+            .chain(
+                by_queue
+                    .values()
+                    .flatten()
+                    .map(|item| match item.is_delayed(now) {
+                        true => WalRecord::Delay { item: item.clone() },
+                        false => WalRecord::Push { item: item.clone() },
+                    }),
+            )
+            .collect();
+        self.log(WalRecord::Batch { records })?;
+
+        let mut dropped = Vec::new();
+        for ((name, items), state) in by_queue.into_iter().zip(locked.iter_mut()) {
+            if let Some(ids) = drop.get(&name) {
+                dropped.extend(state.remove_ids(ids));
+            }
+            for item in items {
+                if item.is_delayed(now) {
+                    state.delay(item);
+                } else {
+                    state.push(item);
+                }
+            }
+        }
+        Ok(dropped)
+    }
+
+    fn preview(
+        &self,
+        queue: &str,
+        offset: usize,
+        limit: Option<usize>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Vec<Item>> {
+        Ok(match self.queue(queue) {
+            Some(q) => self
+                .lock_queue(queue, &q)?
+                .items
+                .iter()
+                .filter(|item| selects(filter, item))
+                .skip(offset)
+                .take(limit.unwrap_or(usize::MAX))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    fn drop_candidates(
+        &self,
+        queue: &str,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> io::Result<Vec<Item>> {
+        let q = match self.queue(queue) {
+            Some(q) => q,
+            None => return Ok(Vec::new()),
+        };
+        let state = self.lock_queue(queue, &q)?;
+        let mut items: Vec<&Item> = state.items.iter().collect();
+        items.sort_by_key(|item| (item.priority(), item.received_epoch()));
+        Ok(items
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect())
+    }
+
+    fn take(
+        &self,
+        queue: &str,
+        limit: Option<usize>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Vec<Item>> {
+        self.remove_front(queue, limit, filter)
+    }
+
+    fn lease(
+        &self,
+        queue: &str,
+        limit: Option<usize>,
+        visibility_timeout: i64,
+        dead_letter: Option<&DeadLetter>,
+        filter: Option<&Filter>,
+    ) -> io::Result<Option<Lease>> {
+        let q = match self.queue(queue) {
+            Some(q) => q,
+            None => return Ok(None),
+        };
+        let limit = limit.unwrap_or(usize::MAX);
+
+        match dead_letter.filter(|policy| policy.queue != queue) {
+            Some(policy) => {
+                let dlq = self.queue_or_create(&policy.queue);
+                let (mut state, mut dead) = self.lock_pair((queue, &q), (&policy.queue, &dlq))?;
+                self.dead_letter_window(queue, &mut state, &mut dead, limit, policy, filter)?;
+                self.lease_locked(queue, &mut state, limit, visibility_timeout, filter)
+            }
+            None => {
+                let mut state = self.lock_queue(queue, &q)?;
+                self.lease_locked(queue, &mut state, limit, visibility_timeout, filter)
+            }
+        }
+    }
+
+    fn ack(&self, queue: &str, receipt: &str) -> io::Result<bool> {
+        self.settle(queue, receipt, false)
+    }
+
+    fn nack(&self, queue: &str, receipt: &str) -> io::Result<bool> {
+        self.settle(queue, receipt, true)
+    }
+
+    fn get(&self, queue: &str, id: &str) -> io::Result<Option<Item>> {
+        Ok(match self.queue(queue) {
+            Some(q) => self.lock_queue(queue, &q)?.get(id).cloned(),
+            None => None,
+        })
+    }
+
+    fn delete(&self, queue: &str, id: &str) -> io::Result<bool> {
+        let q = match self.queue(queue) {
+            Some(q) => q,
+            None => return Ok(false),
+        };
+        let mut state = self.lock_queue(queue, &q)?;
+        if state.get(id).is_none() {
+            return Ok(false);
+        }
+        self.log(WalRecord::Delete {
+            queue: queue.to_string(),
+            id: id.to_string(),
+        })?;
+        Ok(state.delete(id).is_some())
+    }
+
+    fn redrive(&self, queue: &str) -> io::Result<usize> {
+        let dlq = match self.queue(queue) {
+            Some(dlq) => dlq,
+            None => return Ok(0),
+        };
+        let mut sources: Vec<String> = self
+            .lock_queue(queue, &dlq)?
+            .items
+            .iter()
+            .filter_map(|item| item.dead_letter_source().map(str::to_string))
+            .collect();
+        sources.sort();
+        sources.dedup();
+
+        let mut redriven = 0;
+        for source in sources.iter().filter(|source| source.as_str() != queue) {
+            let target = self.queue_or_create(source);
+            let (mut dead, mut target) = self.lock_pair((queue, &dlq), (source, &target))?;
+            self.log(WalRecord::Redrive {
+                queue: queue.to_string(),
+                to: source.clone(),
+            })?;
+            let items = dead.take_dead_letters(source);
+            redriven += items.len();
+            for item in items {
+                target.push(item);
+            }
+        }
+        Ok(redriven)
+    }
+
+    fn expire(&self, now: i64) -> io::Result<Vec<(String, usize)>> {
+        let queues: Vec<(String, Queue)> = self
+            .queues
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, queue)| (name.clone(), queue.clone()))
+            .collect();
+
+        let mut expired = Vec::new();
+        for (name, queue) in queues {
+            let mut state = queue.lock().unwrap();
+            let due = state.items.iter().any(|item| item.is_expired(now))
+                || state.delayed.iter().any(|item| item.is_expired(now))
+                || state
+                    .leases
+                    .values()
+                    .any(|lease| lease.items.iter().any(|item| item.is_expired(now)));
+            if !due {
+                continue;
+            }
+            self.log(WalRecord::Expire {
+                queue: name.clone(),
+                now,
+            })?;
+            expired.push((name, state.expire(now)));
+        }
+        Ok(expired)
+    }
+
+    fn delayed(&self, queue: &str, offset: usize, limit: Option<usize>) -> io::Result<Vec<Item>> {
+        Ok(match self.queue(queue) {
+            Some(q) => self
+                .lock_queue(queue, &q)?
+                .delayed
+                .iter()
+                .skip(offset)
+                .take(limit.unwrap_or(usize::MAX))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    fn count_delayed(&self, queue: &str) -> io::Result<usize> {
+        Ok(match self.queue(queue) {
+            Some(q) => self.lock_queue(queue, &q)?.delayed.len(),
+            None => 0,
+        })
+    }
+
+    fn count(&self, queue: &str) -> io::Result<usize> {
+        Ok(match self.queue(queue) {
+            Some(q) => self.lock_queue(queue, &q)?.items.len(),
+            None => 0,
+        })
+    }
+
+    fn count_matching(&self, queue: &str, filter: &Filter) -> io::Result<usize> {
+        Ok(match self.queue(queue) {
+            Some(q) => self
+                .lock_queue(queue, &q)?
+                .items
+                .iter()
+                .filter(|item| filter.matches(item))
+                .count(),
+            None => 0,
+        })
+    }
+
+    fn purge(&self, queue: &str, before: Option<i64>) -> io::Result<usize> {
+        let q = match self.queue(queue) {
+            Some(q) => q,
+            None => return Ok(0),
+        };
+        let mut state = self.lock_queue(queue, &q)?;
+        if state.is_empty() {
+            return Ok(0);
+        }
+        self.log(WalRecord::Purge {
+            queue: queue.to_string(),
+            before,
+        })?;
+        Ok(state.purge(before))
+    }
+
+    fn usage(&self, queue: &str) -> io::Result<QueueUsage> {
+        Ok(match self.queue(queue) {
+            Some(q) => {
+                let state = self.lock_queue(queue, &q)?;
+                QueueUsage {
+                    items: state.len(),
+                    bytes: state.bytes(),
+                }
+            }
+            None => QueueUsage::default(),
+        })
+    }
+
+    fn memory_bytes(&self) -> usize {
+        let queues: Vec<Queue> = self.queues.read().unwrap().values().cloned().collect();
+        queues
+            .iter()
+            .map(|queue| queue.lock().unwrap().bytes())
+            .sum()
+    }
+
+    fn queues(&self) -> io::Result<Vec<String>> {
+        let queues: Vec<(String, Queue)> = self
+            .queues
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, queue)| (name.clone(), queue.clone()))
+            .collect();
+        let mut names: Vec<String> = queues
+            .into_iter()
+            .filter(|(_, queue)| !queue.lock().unwrap().is_empty())
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn stats(&self, queue: &str) -> io::Result<QueueStats> {
+        let mut stats = QueueStats::default();
+        if let Some(q) = self.queue(queue) {
+            let state = self.lock_queue(queue, &q)?;
+            let leased = state.leases.values().flat_map(|lease| &lease.items);
+            for item in state.items.iter().chain(&state.delayed).chain(leased) {
+                stats.record(item);
+            }
+            stats.ready = state.items.len();
+            stats.delayed = state.delayed.len();
+            stats.in_flight = state.len() - stats.ready - stats.delayed;
+        }
+        Ok(stats)
+    }
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
@@ -1,8 +1,16 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::libs::{
+    dedup::Dedup, definitions::QueueDefinitions, events::Events, exchanges::Exchanges,
+    notifier::Notifiers, schemas::Schemas, storage::Storage, topics::Topics,
+};
 
 pub struct CargoPkgInfo {
     pub name: String,
@@ -14,6 +22,9 @@ pub struct CargoPkgInfo {
 #[derive(Deserialize, Clone, Debug)]
 pub struct TOMLData {
     pub config: Config,
+    pub queues: Option<HashMap<String, QueueConfig>>,
+    pub topics: Option<HashMap<String, TopicConfig>>,
+    pub exchanges: Option<HashMap<String, ExchangeConfig>>,
 }
 
 // Config data stored within TOML Data
@@ -24,6 +35,122 @@ pub struct Config {
     pub write_logs: bool,
     pub write_logs_file: String,
     pub api_keys: Option<Vec<String>>,
+    pub admin_api_keys: Option<Vec<String>>,
+    pub max_batch_size: Option<usize>,
+    pub max_payload_size: Option<usize>,
+    pub max_bulk_payload_size: Option<usize>,
+    pub max_memory_bytes: Option<usize>,
+    pub dedup_window: Option<u64>,
+    pub strict_queues: Option<bool>,
+    pub definitions_file: Option<String>,
+    pub schema_reload_interval: Option<u64>,
+    pub topics_file: Option<String>,
+    pub max_wait: Option<u64>,
+    pub expiry_interval: Option<u64>,
+    pub event_history: Option<usize>,
+    pub storage: Option<StorageBackend>,
+    pub storage_path: Option<String>,
+    pub wal_enabled: Option<bool>,
+    pub wal_file: Option<String>,
+    pub wal_fsync: Option<FsyncPolicy>,
+    pub wal_fsync_interval: Option<u64>,
+    pub wal_compaction_interval: Option<u64>,
+}
+
+// Per-queue config stored within TOML Data, under `[queues.<name>]`
+#[derive(Deserialize, Serialize, Clone, Debug, Default, ToSchema)]
+pub struct QueueConfig {
+    pub max_receives: Option<u32>,
+    pub dead_letter_queue: Option<String>,
+    pub default_ttl: Option<i64>,
+    pub priorities: Option<bool>,
+    pub max_payload_size: Option<usize>,
+    pub max_items: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub overflow: Option<OverflowPolicy>,
+    pub dedup_window: Option<u64>,
+    pub content_dedup: Option<bool>,
+    pub schema: Option<serde_json::Value>,
+    pub schema_file: Option<String>,
+}
+
+impl QueueConfig {
+    // Returns the dead-letter policy, if both its settings are configured
+    pub fn dead_letter(&self) -> Option<DeadLetter> {
+        match (self.max_receives, &self.dead_letter_queue) {
+            (Some(max_receives), Some(queue)) => Some(DeadLetter {
+                max_receives,
+                queue: queue.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Per-topic config stored within TOML Data, under `[topics.<name>]`
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct TopicConfig {
+    pub groups: Option<Vec<String>>,
+    pub retention: Option<u64>,
+}
+
+// Per-exchange config stored within TOML Data, under `[exchanges.<name>]`
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ExchangeConfig {
+    pub bindings: Option<Vec<BindingConfig>>,
+}
+
+// Queue an exchange adds items to, when they match both the binding's `routing_key` and `filter`.
+// Bindings without either match every item
+#[derive(Deserialize, Serialize, Clone, Debug, ToSchema)]
+pub struct BindingConfig {
+    pub queue: String,
+    // Dot separated words, where `*` matches exactly one word and `#` any number of them
+    pub routing_key: Option<String>,
+    // JMESPath expression evaluated against the item, such as `content.type == 'refund'`
+    pub filter: Option<String>,
+}
+
+// What happens to items added to a queue already at its `max_items` or `max_bytes`
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    // The new items are refused
+    #[default]
+    Reject,
+    // Items at the front of the queue are removed to make room
+    DropOldest,
+    // The new items are discarded
+    DropNew,
+}
+
+// Items delivered more than `max_receives` times are moved to `queue`
+#[derive(Clone, Debug)]
+pub struct DeadLetter {
+    pub max_receives: u32,
+    pub queue: String,
+}
+
+// Where queued items are stored
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    // In memory, optionally persisted by the write-ahead log
+    Memory,
+    // On disk, in an embedded sled database
+    Sled,
+}
+
+// When the write-ahead log is flushed to disk
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FsyncPolicy {
+    // After every record
+    Always,
+    // Periodically, every `wal_fsync_interval` seconds
+    Interval,
+    // Left to the operating system
+    Never,
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////
@@ -32,9 +159,28 @@ pub struct Config {
 // Actix Application global state
 pub struct AppState {
     pub start_time: DateTime<Utc>,
-    pub item_queue: Arc<Mutex<Vec<Item>>>,
+    pub storage: Arc<dyn Storage>,
     pub api_keys: Vec<String>,
+    pub admin_api_keys: Vec<String>,
+    pub max_batch_size: Option<usize>,
+    pub max_payload_size: usize,
+    pub max_bulk_payload_size: usize,
+    pub max_memory_bytes: Option<usize>,
+    pub max_wait: u64,
+    pub dedup_window: u64,
+    pub queues: Arc<QueueDefinitions>,
+    pub strict_queues: bool,
+    pub expired: ExpiredTotals,
+    pub notifiers: Arc<Notifiers>,
+    pub events: Arc<Events>,
+    pub dedup: Arc<Dedup>,
+    pub schemas: Arc<Schemas>,
+    pub topics: Arc<Topics>,
+    pub exchanges: Arc<Exchanges>,
 }
+// Running totals of items removed by expiry, by queue
+pub type ExpiredTotals = Arc<Mutex<HashMap<String, u64>>>;
+
 // Global state impls
 impl AppState {
     // Returns current uptime using `start_time`
@@ -46,7 +192,83 @@ impl AppState {
         let minutes = duration.num_minutes() % 60;
         let seconds = duration.num_seconds() % 60;
 
-        return format!("{days:02} {hours:02}:{minutes:02}:{seconds:02}",);
+        format!("{days:02} {hours:02}:{minutes:02}:{seconds:02}",)
+    }
+
+    // Returns true if no api keys are configured, leaving every ordinary request authorized
+    pub fn auth_disabled(&self) -> bool {
+        self.api_keys.is_empty()
+    }
+
+    // Returns true if no keys are configured at all, leaving privileged requests authorized too
+    pub fn admin_auth_disabled(&self) -> bool {
+        self.auth_disabled() && self.admin_api_keys.is_empty()
+    }
+
+    // Returns whether items can be added to a queue, which they cannot in strict mode unless it is declared
+    pub fn accepts(&self, queue: &str) -> bool {
+        !self.strict_queues || self.queues.contains(queue)
+    }
+
+    // Returns the dead-letter policy of a queue, if it has one
+    pub fn dead_letter(&self, queue: &str) -> Option<DeadLetter> {
+        self.queues.with(queue, |config| config.dead_letter())
+    }
+
+    // Returns the default time to live of items in a queue, if it has one
+    pub fn default_ttl(&self, queue: &str) -> Option<i64> {
+        self.queues.with(queue, |config| config.default_ttl)
+    }
+
+    // Returns whether a queue orders items by priority, which it does unless disabled
+    pub fn priorities(&self, queue: &str) -> bool {
+        self.queues
+            .with(queue, |config| config.priorities)
+            .unwrap_or(true)
+    }
+
+    // Returns the largest item a queue accepts in bytes, its own limit or else the server's
+    pub fn payload_limit(&self, queue: &str) -> usize {
+        self.queues
+            .with(queue, |config| config.max_payload_size)
+            .unwrap_or(self.max_payload_size)
+    }
+
+    // Returns the largest item any queue accepts in bytes
+    pub fn largest_payload_limit(&self) -> usize {
+        self.queues
+            .each(|config| config.max_payload_size)
+            .into_iter()
+            .fold(self.max_payload_size, usize::max)
+    }
+
+    // Returns what happens to items added to a queue that is full
+    pub fn overflow(&self, queue: &str) -> OverflowPolicy {
+        self.queues
+            .with(queue, |config| config.overflow)
+            .unwrap_or_default()
+    }
+
+    // Returns the number of seconds a queue remembers deduplication keys for, its own window or else the server's
+    pub fn dedup_window(&self, queue: &str) -> u64 {
+        self.queues
+            .with(queue, |config| config.dedup_window)
+            .unwrap_or(self.dedup_window)
+    }
+
+    // Returns whether items in a queue are deduplicated by their content when they have no key of their own
+    pub fn content_dedup(&self, queue: &str) -> bool {
+        self.queues
+            .with(queue, |config| config.content_dedup)
+            .unwrap_or(false)
+    }
+
+    // Returns the number of items a request may receive, capped by `max_batch_size`
+    pub fn batch_limit(&self, requested: Option<usize>) -> Option<usize> {
+        match (requested, self.max_batch_size) {
+            (Some(requested), Some(max)) => Some(requested.min(max)),
+            (requested, max) => requested.or(max),
+        }
     }
 }
 
@@ -57,6 +279,22 @@ pub struct WebError {
     pub error: String,
 }
 
+// Reponse error for item content not matching its queue's schema
+#[derive(Serialize, ToSchema)]
+pub struct WebValidationError {
+    pub timestamp: String,
+    pub error: String,
+    pub violations: Vec<WebViolation>,
+}
+
+// Part of an item's content that does not match its queue's schema
+#[derive(Serialize, ToSchema)]
+pub struct WebViolation {
+    // JSON pointer to the offending value within `content`, empty for `content` itself
+    pub path: String,
+    pub message: String,
+}
+
 // Web route 'health' response body
 #[derive(Serialize, ToSchema)]
 pub struct WebHealth {
@@ -66,17 +304,401 @@ pub struct WebHealth {
 // Web route 'health' response body
 #[derive(Deserialize, Serialize, Clone, ToSchema)]
 pub struct Meta {
+    // Server generated identifier, unique across all queues
+    #[serde(default)]
+    pub id: String,
     pub received_epoch: i64,
+    // Number of times the item has been leased
+    #[serde(default)]
+    pub receive_count: u32,
+    // Queue the item was dead-lettered from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dead_letter_source: Option<String>,
+    // When the item expires and is removed from the queue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_epoch: Option<i64>,
+    // When the item becomes visible to fetches and previews, if it was delayed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deliver_epoch: Option<i64>,
+    // Size of the item in bytes as it was received
+    #[serde(default)]
+    pub size: usize,
+    // Position of the item in its topic, if it was published to one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+}
+
+// Web route 'fetch_items' query parameters
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FetchQuery {
+    /// Maximum number of items to fetch, oldest first
+    pub limit: Option<usize>,
+    /// Lease items for this many seconds instead of removing them. Leased items must be acknowledged, or they are returned to the queue
+    pub visibility_timeout: Option<i64>,
+    /// Seconds to wait for items to arrive when the queue is empty, up to the configured `max_wait`
+    pub wait: Option<u64>,
+    /// Only fetch items matching this JMESPath expression, evaluated against each item with its `content` and `meta`.
+    /// For example `content.type == 'invoice'`. Other items are left in the queue
+    pub filter: Option<String>,
+    /// Only fetch items received at or after this epoch
+    pub since: Option<i64>,
+    /// Only fetch items received before this epoch
+    pub until: Option<i64>,
+}
+
+// Web route 'subscribe' query parameters
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WsQuery {
+    /// Lease each item for this many seconds instead of removing it. Leases are acknowledged with `{"ack": "<receipt>"}` frames, or returned to the queue with `{"nack": "<receipt>"}`
+    pub visibility_timeout: Option<i64>,
+    /// Maximum number of messages sent but not yet acknowledged, 100 unless set. Without `visibility_timeout`, items are acknowledged with `{"ack": "<id>"}` frames within 30 seconds, or are sent again
+    pub prefetch: Option<usize>,
+}
+
+// Acknowledgement frame sent by a WebSocket subscriber
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WsFrame {
+    Ack(String),
+    Nack(String),
+}
+
+// Web route 'get_items' query parameters
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PreviewQuery {
+    /// Number of items to skip, oldest first. Use the `X-Next-Offset` response header to page through a queue
+    pub offset: Option<usize>,
+    /// Maximum number of items to return
+    pub limit: Option<usize>,
+    /// Only return items matching this JMESPath expression, evaluated against each item with its `content` and `meta`.
+    /// For example `content.type == 'invoice'`. Offsets and `X-Total-Count` only count matching items
+    pub filter: Option<String>,
+    /// Only return items received at or after this epoch
+    pub since: Option<i64>,
+    /// Only return items received before this epoch
+    pub until: Option<i64>,
+    /// Sort items by when they were received, instead of the order they would be fetched in
+    pub order: Option<PreviewOrder>,
+}
+
+// Order of previewed items by when they were received
+#[derive(Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewOrder {
+    Oldest,
+    Newest,
+}
+
+// Web route 'get_delayed_items' query parameters
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DelayedQuery {
+    /// Number of items to skip, soonest first. Use the `X-Next-Offset` response header to page through a queue
+    pub offset: Option<usize>,
+    /// Maximum number of items to return
+    pub limit: Option<usize>,
+}
+
+// Web route 'purge_queue' query parameters
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PurgeQuery {
+    /// Only remove items received before this epoch
+    pub before: Option<i64>,
+}
+
+// Web route 'add_item' response body
+#[derive(Serialize, ToSchema)]
+pub struct WebItemId {
+    pub id: String,
+}
+
+// Web route 'add_items' response body
+#[derive(Serialize, ToSchema)]
+pub struct WebItemIds {
+    pub ids: Vec<String>,
+    // Entries discarded because their queue was full, counting from 1
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dropped: Vec<usize>,
+}
+
+// Web route 'add_items' rejected entry
+#[derive(Serialize, ToSchema)]
+pub struct WebEntryError {
+    // Position of the entry counting from 1, its line number for NDJSON
+    pub entry: usize,
+    pub error: String,
+}
+
+// Web route 'add_items' error response body
+#[derive(Serialize, ToSchema)]
+pub struct WebBulkError {
+    pub timestamp: String,
+    pub errors: Vec<WebEntryError>,
+}
+
+// Web route 'list_queues' and 'get_queue' response body
+#[derive(Serialize, ToSchema)]
+pub struct WebQueueStats {
+    pub queue: String,
+    // Items ready for delivery
+    pub items: usize,
+    // Items leased and waiting to be acknowledged
+    pub in_flight: usize,
+    // Items held back until their delivery time
+    pub delayed: usize,
+    // Total size of every item, ready, leased or delayed
+    pub bytes: usize,
+    // Earliest and latest `received_epoch` of every item, ready, leased or delayed
+    pub oldest_received_epoch: Option<i64>,
+    pub newest_received_epoch: Option<i64>,
+    // Items removed by expiry since the server started
+    pub expired: u64,
+}
+
+// Web route 'ack_items' and 'nack_items' request body
+#[derive(Deserialize, ToSchema)]
+pub struct WebReceipt {
+    pub receipt: String,
+}
+
+// Web route 'redrive_items' response body
+#[derive(Serialize, ToSchema)]
+pub struct WebRedrive {
+    pub redriven: usize,
+}
+
+// Web route 'list_definitions' response body entry
+#[derive(Serialize, ToSchema)]
+pub struct WebQueueDefinition {
+    pub queue: String,
+    pub config: QueueConfig,
+}
+
+// Web route 'purge_queue' response body
+#[derive(Serialize, ToSchema)]
+pub struct WebPurge {
+    pub purged: usize,
+}
+
+// Web route 'publish_item' request body
+#[derive(Deserialize, ToSchema)]
+pub struct WebTopicItem {
+    pub content: serde_json::Value,
+}
+
+// Web route 'publish_item' response body
+#[derive(Serialize, ToSchema)]
+pub struct WebPublished {
+    pub id: String,
+    pub offset: u64,
+}
+
+// Web route 'list_topics' and 'get_topic' response body
+#[derive(Serialize, ToSchema)]
+pub struct WebTopicStats {
+    pub topic: String,
+    // Items held, waiting for a group to read them or for the topic's retention to pass
+    pub items: usize,
+    pub bytes: usize,
+    // Offset of the oldest item held, and of the next item published
+    pub first_offset: u64,
+    pub next_offset: u64,
+    pub oldest_received_epoch: Option<i64>,
+    pub newest_received_epoch: Option<i64>,
+    // Seconds items are kept for, whether or not every group has read them
+    pub retention: Option<u64>,
+    pub groups: Vec<WebGroupStats>,
+}
+
+// Web route 'list_topics' and 'get_topic' consumer group, and 'set_group' response body
+#[derive(Serialize, ToSchema)]
+pub struct WebGroupStats {
+    pub group: String,
+    // Offset of the next item the group reads
+    pub offset: u64,
+    // Items published that the group has not read yet
+    pub lag: u64,
+}
+
+// Web route 'set_group' request body
+#[derive(Deserialize, ToSchema)]
+pub struct WebGroupOffset {
+    // Offset to read from next, which is kept within the items the topic holds
+    pub offset: Option<u64>,
+}
+
+// Web route 'read_topic' query parameters
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TopicFetchQuery {
+    /// Maximum number of items to read, oldest first
+    pub limit: Option<usize>,
+    /// Seconds to wait for items to be published when the group has read every item, up to the configured `max_wait`
+    pub wait: Option<u64>,
+}
+
+// Web route 'publish_to_exchange' request body
+#[derive(Deserialize, ToSchema)]
+pub struct WebExchangeItem {
+    // Dot separated words bindings are matched against, such as `orders.eu.paid`
+    pub routing_key: Option<String>,
+    pub content: serde_json::Value,
+    #[serde(default)]
+    pub priority: Option<i64>,
+    #[serde(default)]
+    pub ttl: Option<i64>,
+    #[serde(default)]
+    pub delay_seconds: Option<i64>,
+    #[serde(default)]
+    pub deliver_at: Option<i64>,
+    #[serde(default)]
+    pub dedup_id: Option<String>,
+}
+
+// Web route 'publish_to_exchange' response body
+#[derive(Serialize, ToSchema)]
+pub struct WebRouted {
+    pub items: Vec<WebRoutedItem>,
+    // Queues the item was discarded by because they were full
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dropped: Vec<String>,
+}
+
+// Web route 'publish_to_exchange' item added to a queue, with the original's id for repeated items
+#[derive(Serialize, ToSchema)]
+pub struct WebRoutedItem {
+    pub queue: String,
+    pub id: String,
+}
+
+// Web route 'list_exchanges' response body entry
+#[derive(Serialize, ToSchema)]
+pub struct WebExchange {
+    pub exchange: String,
+    pub bindings: Vec<BindingConfig>,
+}
+
+// Items delivered but not yet acknowledged
+#[derive(Deserialize, Serialize, Clone, ToSchema)]
+pub struct Lease {
+    pub receipt: String,
+    pub expires_epoch: i64,
+    pub items: Vec<Item>,
 }
 
 // Item to be queued
 #[derive(Deserialize, Serialize, Clone, ToSchema)]
 pub struct Item {
+    // Left out when the item is published to an `exchange` instead
+    #[serde(default)]
     pub queue: String,
     pub content: serde_json::Value,
+    // Items with a higher priority are delivered first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i64>,
+    // Seconds until the item expires and is removed from the queue
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<i64>,
+    // Seconds before the item can be delivered, up to a year
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay_seconds: Option<i64>,
+    // When the item can be delivered, instead of `delay_seconds`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deliver_at: Option<i64>,
+    // Key identifying repeats of the item, which are not added again within the queue's `dedup_window`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedup_id: Option<String>,
+    // Exchange the item is published to instead of a queue, only read when it is added
+    #[serde(default, skip_serializing)]
+    pub exchange: Option<String>,
+    // Dot separated words the exchange's bindings are matched against, such as `orders.eu.paid`
+    #[serde(default, skip_serializing)]
+    pub routing_key: Option<String>,
     pub meta: Option<Meta>,
 }
 
+impl Item {
+    pub fn receive_count(&self) -> u32 {
+        self.meta.as_ref().map_or(0, |meta| meta.receive_count)
+    }
+
+    // True if delivering the item again would exceed the policy's `max_receives`
+    pub fn exceeds(&self, policy: &DeadLetter) -> bool {
+        self.receive_count() >= policy.max_receives
+    }
+
+    // Counts one more delivery of the item
+    pub fn record_receive(&mut self) {
+        if let Some(meta) = self.meta.as_mut() {
+            meta.receive_count += 1;
+        }
+    }
+
+    // Moves the item to a dead-letter queue, remembering where it came from
+    pub fn dead_letter(&mut self, dead_letter_queue: &str) {
+        let source = std::mem::replace(&mut self.queue, dead_letter_queue.to_string());
+        if let Some(meta) = self.meta.as_mut() {
+            meta.dead_letter_source = Some(source);
+        }
+    }
+
+    // Returns a dead-lettered item to the queue it came from, with a fresh delivery count
+    pub fn redrive(&mut self) {
+        if let Some(meta) = self.meta.as_mut() {
+            if let Some(source) = meta.dead_letter_source.take() {
+                self.queue = source;
+                meta.receive_count = 0;
+            }
+        }
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.meta
+            .as_ref()
+            .and_then(|meta| meta.expires_epoch)
+            .is_some_and(|expires_epoch| expires_epoch <= now)
+    }
+
+    pub fn size(&self) -> usize {
+        self.meta.as_ref().map_or(0, |meta| meta.size)
+    }
+
+    pub fn priority(&self) -> i64 {
+        self.priority.unwrap_or_default()
+    }
+
+    pub fn deliver_epoch(&self) -> i64 {
+        self.meta
+            .as_ref()
+            .and_then(|meta| meta.deliver_epoch)
+            .unwrap_or_default()
+    }
+
+    pub fn is_delayed(&self, now: i64) -> bool {
+        self.deliver_epoch() > now
+    }
+
+    pub fn received_epoch(&self) -> Option<i64> {
+        self.meta.as_ref().map(|meta| meta.received_epoch)
+    }
+
+    pub fn offset(&self) -> Option<u64> {
+        self.meta.as_ref().and_then(|meta| meta.offset)
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.meta.as_ref().map(|meta| meta.id.as_str())
+    }
+
+    pub fn dead_letter_source(&self) -> Option<&str> {
+        self.meta.as_ref()?.dead_letter_source.as_deref()
+    }
+}
+
 /*
 ########################################################################################################
 #   Copyright (C) 2022 Coombszy
@@ -0,0 +1,413 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_web::rt;
+use chrono::Utc;
+use log::{error, info, warn};
+
+use crate::libs::{
+    storage::{QueueStats, Storage},
+    structs::{Item, TopicConfig},
+};
+
+// Default file consumer group offsets are saved to
+pub const DEFAULT_TOPICS_FILE: &str = "./data/topics.json";
+// Prefix of the storage queues holding topics, keeps them apart from ordinary queues
+pub const TOPIC_QUEUE_PREFIX: &str = "topic:";
+// Queue names that are literal segments of `/items/{name}/{queue}` routes, which `/items/{queue}/{id}` would shadow
+const ROUTE_QUEUE_NAMES: [&str; 2] = ["preview", "delayed"];
+// Items looked at per page when finding the items older than a topic's retention
+const RETENTION_PAGE_SIZE: usize = 100;
+
+// Topics declared in `[topics.<name>]` config sections. Each is a log of items that every consumer
+// group reads in full, from its own offset
+pub struct Topics {
+    storage: Arc<dyn Storage>,
+    topics: HashMap<String, Mutex<Topic>>,
+    // Offset of every consumer group, by topic, as saved at `path`
+    offsets: Mutex<BTreeMap<String, BTreeMap<String, u64>>>,
+    path: PathBuf,
+}
+
+// Log of a single topic. Every change to its items is made holding its lock, so offsets always
+// line up with positions in its storage queue
+struct Topic {
+    queue: String,
+    // Seconds items are kept for, whether or not every group has read them
+    retention: Option<u64>,
+    // Offset the next item published is given
+    next: u64,
+    // Offset of the next item each consumer group reads
+    groups: BTreeMap<String, u64>,
+}
+
+// Where a topic's log starts and ends, and where each of its consumer groups is
+pub struct TopicOffsets {
+    pub first: u64,
+    pub next: u64,
+    pub retention: Option<u64>,
+    pub groups: BTreeMap<String, u64>,
+    pub stats: QueueStats,
+}
+
+// Name of the storage queue holding a topic's items
+pub fn topic_queue(topic: &str) -> String {
+    format!("{TOPIC_QUEUE_PREFIX}{topic}")
+}
+
+// Refuses queue names that belong to topics. Their storage queues are only changed through the topic
+// routes, so that every offset stays in line with its item. Names taken by routes, such as `preview` in
+// `/items/preview/{queue}`, are refused too, as their items could not be reached by id
+pub fn check_queue_name(queue: &str) -> Result<(), String> {
+    if queue.starts_with(TOPIC_QUEUE_PREFIX) {
+        return Err(format!(
+            "queue '{}' is reserved, names starting with '{}' hold topics",
+            queue, TOPIC_QUEUE_PREFIX
+        ));
+    }
+    if ROUTE_QUEUE_NAMES.contains(&queue) {
+        return Err(format!(
+            "queue '{}' is reserved, it is part of the `/items/{}/{{queue}}` route",
+            queue, queue
+        ));
+    }
+    Ok(())
+}
+
+impl Topics {
+    // Opens the topics declared in config over the items already in storage, with the group offsets
+    // saved at `path`. Groups declared in config that have no saved offset start at the oldest item
+    pub fn load(
+        storage: Arc<dyn Storage>,
+        configs: HashMap<String, TopicConfig>,
+        path: PathBuf,
+    ) -> io::Result<Topics> {
+        let mut saved: BTreeMap<String, BTreeMap<String, u64>> = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?)?
+        } else {
+            BTreeMap::new()
+        };
+
+        let mut topics = HashMap::new();
+        let mut offsets = BTreeMap::new();
+        for (name, config) in configs {
+            let queue = topic_queue(&name);
+            let mut groups = saved.remove(&name).unwrap_or_default();
+            // With no items left, the log carries on from the furthest any group has read
+            let count = storage.count(&queue)?;
+            let next = match count.checked_sub(1) {
+                Some(last) => offset_at(&storage, &queue, last)?.map_or(0, |offset| offset + 1),
+                None => groups.values().max().copied().unwrap_or(0),
+            };
+            let first = offset_at(&storage, &queue, 0)?.unwrap_or(next);
+            for group in config.groups.unwrap_or_default() {
+                groups.entry(group).or_insert(first);
+            }
+            for offset in groups.values_mut() {
+                *offset = (*offset).clamp(first, next);
+            }
+            info!(
+                "Opened topic '{}' with {} items and {} consumer groups",
+                name,
+                count,
+                groups.len()
+            );
+
+            offsets.insert(name.clone(), groups.clone());
+            topics.insert(
+                name,
+                Mutex::new(Topic {
+                    queue,
+                    retention: config.retention,
+                    next,
+                    groups,
+                }),
+            );
+        }
+
+        // Groups of topics no longer declared are dropped
+        write_offsets(&path, &offsets)?;
+        Ok(Topics {
+            storage,
+            topics,
+            offsets: Mutex::new(offsets),
+            path,
+        })
+    }
+
+    pub fn contains(&self, topic: &str) -> bool {
+        self.topics.contains_key(topic)
+    }
+
+    // Every declared topic, by name
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.topics.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // True if the topic has the consumer group
+    pub fn has_group(&self, topic: &str, group: &str) -> bool {
+        self.topics
+            .get(topic)
+            .is_some_and(|state| state.lock().unwrap().groups.contains_key(group))
+    }
+
+    // Appends an item to a topic, returning the offset it was given. None if the topic is not declared
+    pub fn publish(&self, topic: &str, mut item: Item) -> io::Result<Option<u64>> {
+        let mut state = match self.topics.get(topic) {
+            Some(state) => state.lock().unwrap(),
+            None => return Ok(None),
+        };
+        let offset = state.next;
+        item.queue = state.queue.clone();
+        if let Some(meta) = item.meta.as_mut() {
+            meta.offset = Some(offset);
+        }
+        self.storage.push(item)?;
+        state.next += 1;
+        Ok(Some(offset))
+    }
+
+    // Returns up to `limit` items a consumer group has not read yet, oldest first, and moves the group
+    // past them. None if the topic or group does not exist
+    pub fn read(
+        &self,
+        topic: &str,
+        group: &str,
+        limit: Option<usize>,
+    ) -> io::Result<Option<Vec<Item>>> {
+        let mut state = match self.topics.get(topic) {
+            Some(state) => state.lock().unwrap(),
+            None => return Ok(None),
+        };
+        let offset = match state.groups.get(group) {
+            Some(offset) => *offset,
+            None => return Ok(None),
+        };
+        let first = self.first(&state)?;
+        let items = self.storage.preview(
+            &state.queue,
+            (offset.max(first) - first) as usize,
+            limit,
+            None,
+        )?;
+        let read_to = match items.last().and_then(Item::offset) {
+            Some(last) => last + 1,
+            None => return Ok(Some(items)),
+        };
+
+        let mut groups = state.groups.clone();
+        groups.insert(group.to_string(), read_to);
+        self.save(topic, &groups)?;
+        state.groups = groups;
+        self.trim(topic, &state, first);
+        Ok(Some(items))
+    }
+
+    // Adds a consumer group, or moves one, to read from `offset` on. Without an offset new groups
+    // start at the oldest item and existing groups stay where they are. Offsets are kept within the
+    // items the topic holds. Returns where the group now is, and whether it was added, or None if
+    // the topic is not declared
+    pub fn register(
+        &self,
+        topic: &str,
+        group: &str,
+        offset: Option<u64>,
+    ) -> io::Result<Option<(u64, bool)>> {
+        let mut state = match self.topics.get(topic) {
+            Some(state) => state.lock().unwrap(),
+            None => return Ok(None),
+        };
+        let first = self.first(&state)?;
+        let current = state.groups.get(group).copied();
+        let offset = offset.or(current).unwrap_or(first).clamp(first, state.next);
+
+        let mut groups = state.groups.clone();
+        groups.insert(group.to_string(), offset);
+        self.save(topic, &groups)?;
+        state.groups = groups;
+        self.trim(topic, &state, first);
+        Ok(Some((offset, current.is_none())))
+    }
+
+    // Removes a consumer group, so it no longer holds back items it has not read.
+    // False if the topic or group does not exist
+    pub fn unregister(&self, topic: &str, group: &str) -> io::Result<bool> {
+        let mut state = match self.topics.get(topic) {
+            Some(state) => state.lock().unwrap(),
+            None => return Ok(false),
+        };
+        if !state.groups.contains_key(group) {
+            return Ok(false);
+        }
+
+        let mut groups = state.groups.clone();
+        groups.remove(group);
+        self.save(topic, &groups)?;
+        state.groups = groups;
+        let first = self.first(&state)?;
+        self.trim(topic, &state, first);
+        Ok(true)
+    }
+
+    // Where a topic's log starts and ends, and where each of its groups is. None if it is not declared
+    pub fn offsets(&self, topic: &str) -> io::Result<Option<TopicOffsets>> {
+        let state = match self.topics.get(topic) {
+            Some(state) => state.lock().unwrap(),
+            None => return Ok(None),
+        };
+        Ok(Some(TopicOffsets {
+            first: self.first(&state)?,
+            next: state.next,
+            retention: state.retention,
+            groups: state.groups.clone(),
+            stats: self.storage.stats(&state.queue)?,
+        }))
+    }
+
+    // Removes items older than their topic's retention, whether or not every group has read them.
+    // Groups that had not read them move on to the oldest item left. Returns how many items were
+    // removed from each topic that had any
+    pub fn expire(&self, now: i64) -> io::Result<Vec<(String, usize)>> {
+        let mut expired = Vec::new();
+        for name in self.names() {
+            let mut state = self.topics[&name].lock().unwrap();
+            let cutoff = match state.retention {
+                Some(retention) => now - retention as i64,
+                None => continue,
+            };
+
+            // Items are received in offset order, so the expired ones are all at the front
+            let mut count = 0;
+            loop {
+                let page =
+                    self.storage
+                        .preview(&state.queue, count, Some(RETENTION_PAGE_SIZE), None)?;
+                let old = page
+                    .iter()
+                    .take_while(|item| item.received_epoch().is_some_and(|r| r < cutoff))
+                    .count();
+                count += old;
+                if old < RETENTION_PAGE_SIZE {
+                    break;
+                }
+            }
+            if count == 0 {
+                continue;
+            }
+
+            self.storage.take(&state.queue, Some(count), None)?;
+            let first = self.first(&state)?;
+            let behind: Vec<String> = state
+                .groups
+                .iter()
+                .filter(|(_, offset)| **offset < first)
+                .map(|(group, _)| group.clone())
+                .collect();
+            if !behind.is_empty() {
+                warn!(
+                    "Consumer groups {:?} of topic '{}' missed items that expired before they were read",
+                    behind, name
+                );
+                let mut groups = state.groups.clone();
+                for offset in groups.values_mut() {
+                    *offset = (*offset).max(first);
+                }
+                self.save(&name, &groups)?;
+                state.groups = groups;
+            }
+            expired.push((name.clone(), count));
+        }
+        Ok(expired)
+    }
+
+    // Offset of the oldest item a topic holds, or of the next item published if it holds none
+    fn first(&self, state: &Topic) -> io::Result<u64> {
+        Ok(offset_at(&self.storage, &state.queue, 0)?.unwrap_or(state.next))
+    }
+
+    // Removes the items every consumer group has read. Items stay for groups added later,
+    // until the topic's retention passes, when it has no groups
+    fn trim(&self, topic: &str, state: &Topic, first: u64) {
+        let read = match state.groups.values().min() {
+            Some(read) if *read > first => *read,
+            _ => return,
+        };
+        if let Err(e) = self
+            .storage
+            .take(&state.queue, Some((read - first) as usize), None)
+        {
+            error!("Failed to remove read items from topic '{}': {}", topic, e);
+        }
+    }
+
+    // Saves the offsets of a topic's groups, so they carry on from there after a restart.
+    // Saves are made one at a time, so none is lost to another made at the same time
+    fn save(&self, topic: &str, groups: &BTreeMap<String, u64>) -> io::Result<()> {
+        let mut offsets = self.offsets.lock().unwrap();
+        let mut saved = offsets.clone();
+        saved.insert(topic.to_string(), groups.clone());
+        write_offsets(&self.path, &saved)?;
+        *offsets = saved;
+        Ok(())
+    }
+}
+
+fn write_offsets(path: &Path, offsets: &BTreeMap<String, BTreeMap<String, u64>>) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_vec_pretty(offsets)?)?;
+    fs::rename(&tmp_path, path)
+}
+
+// Offset of the item at `index` in a topic's storage queue
+fn offset_at(storage: &Arc<dyn Storage>, queue: &str, index: usize) -> io::Result<Option<u64>> {
+    Ok(storage
+        .preview(queue, index, Some(1), None)?
+        .first()
+        .and_then(Item::offset))
+}
+
+// Periodically removes items older than their topic's retention
+pub fn spawn_retention_task(topics: Arc<Topics>, interval: u64) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(Duration::from_secs(interval.max(1)));
+        loop {
+            interval.tick().await;
+            match topics.expire(Utc::now().timestamp()) {
+                Ok(expired) => {
+                    for (topic, count) in expired {
+                        info!("Removed {} expired items from topic '{}'", count, topic);
+                    }
+                }
+                Err(e) => error!("Failed to remove expired topic items: {}", e),
+            }
+        }
+    });
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
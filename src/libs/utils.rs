@@ -1,7 +1,15 @@
 use log::debug;
 
-use crate::libs::structs::TOMLData;
-use std::{fs, process::exit};
+use crate::libs::{
+    exchanges::{words, MAX_ROUTING_KEY_WORDS},
+    structs::{QueueConfig, TOMLData},
+    topics::check_queue_name,
+};
+use std::{
+    fs,
+    path::{Component, Path},
+    process::exit,
+};
 
 use super::structs::{AppState, CargoPkgInfo};
 
@@ -30,13 +38,123 @@ pub fn load_config_toml(filename: String) -> TOMLData {
     config_data
 }
 
+// Exits if the loaded config is inconsistent
+pub fn validate_config(toml_data: &TOMLData) {
+    let mut errors: Vec<String> = Vec::new();
+
+    for (name, queue) in toml_data.queues.iter().flatten() {
+        errors.extend(validate_queue(name, queue));
+    }
+    for (name, topic) in toml_data.topics.iter().flatten() {
+        if topic.retention == Some(0) {
+            errors.push(format!("topic '{name}' retention must be greater than 0"));
+        }
+        if topic.groups.iter().flatten().any(|group| group.is_empty()) {
+            errors.push(format!(
+                "topic '{name}' consumer group names must not be empty"
+            ));
+        }
+    }
+    for (name, exchange) in toml_data.exchanges.iter().flatten() {
+        for binding in exchange.bindings.iter().flatten() {
+            if binding.queue.is_empty() {
+                errors.push(format!("exchange '{name}' has a binding without a queue"));
+            }
+            if let Err(e) = check_queue_name(&binding.queue) {
+                errors.push(format!("exchange '{name}' binding is invalid. {e}"));
+            }
+            if binding
+                .routing_key
+                .as_deref()
+                .is_some_and(|key| words(key).contains(&""))
+            {
+                errors.push(format!(
+                    "exchange '{name}' binding to '{}' has an empty word in its routing_key",
+                    binding.queue
+                ));
+            }
+            if binding
+                .routing_key
+                .as_deref()
+                .is_some_and(|key| words(key).len() > MAX_ROUTING_KEY_WORDS)
+            {
+                errors.push(format!(
+                    "exchange '{name}' binding to '{}' has more than {MAX_ROUTING_KEY_WORDS} words in its routing_key",
+                    binding.queue
+                ));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        println!("Invalid config");
+        for error in errors {
+            println!("Error: {}", error);
+        }
+        exit(1);
+    }
+}
+
+// Returns every inconsistency in a queue's config
+pub fn validate_queue(name: &str, queue: &QueueConfig) -> Vec<String> {
+    let mut errors: Vec<String> = Vec::new();
+    errors.extend(check_queue_name(name).err());
+    errors.extend(
+        queue
+            .dead_letter_queue
+            .as_deref()
+            .and_then(|dead_letter_queue| check_queue_name(dead_letter_queue).err()),
+    );
+    match (&queue.max_receives, &queue.dead_letter_queue) {
+        (Some(_), None) | (None, Some(_)) => errors.push(format!(
+            "queue '{name}' must set both max_receives and dead_letter_queue, or neither"
+        )),
+        (_, Some(dead_letter_queue)) if dead_letter_queue == name => errors.push(format!(
+            "queue '{name}' cannot be its own dead_letter_queue"
+        )),
+        _ => {}
+    }
+    if matches!(queue.default_ttl, Some(ttl) if ttl <= 0) {
+        errors.push(format!("queue '{name}' default_ttl must be greater than 0"));
+    }
+    if queue.max_items == Some(0) || queue.max_bytes == Some(0) {
+        errors.push(format!(
+            "queue '{name}' max_items and max_bytes must be greater than 0"
+        ));
+    }
+    if queue.schema.is_some() && queue.schema_file.is_some() {
+        errors.push(format!(
+            "queue '{name}' can set only one of schema and schema_file"
+        ));
+    }
+    // Schema files are read from the config directory, and nowhere else
+    let outside = |file: &String| {
+        let path = Path::new(file);
+        path.is_absolute()
+            || path
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+    };
+    if queue.schema_file.as_ref().is_some_and(outside) {
+        errors.push(format!(
+            "queue '{name}' schema_file must be a path within the config directory"
+        ));
+    }
+    errors
+}
+
 // Function that returns true if an api key is valid, else false
 pub fn validate_api_key(app_state: &AppState, key: &String) -> bool {
     debug!(
         "API key in - \"{}\" vs Accepted keys: {:?}",
         key, app_state.api_keys
     );
-    app_state.api_keys.contains(key)
+    app_state.api_keys.contains(key) || app_state.admin_api_keys.contains(key)
+}
+
+// Function that returns true if an api key is allowed privileged requests, else false
+pub fn validate_admin_api_key(app_state: &AppState, key: &String) -> bool {
+    app_state.admin_api_keys.contains(key)
 }
 
 // Draws start screen containing app version and ascii
@@ -0,0 +1,345 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::libs::{
+    storage::memory::QueueState,
+    structs::{FsyncPolicy, Item, Lease},
+};
+
+// Single operation recorded in the write-ahead log
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum WalRecord {
+    // Item added behind every other item of the same or higher priority
+    Push {
+        item: Item,
+    },
+    // Records applied together, so a crash never leaves only some of them applied
+    Batch {
+        records: Vec<WalRecord>,
+    },
+    // Item held back until its delivery time
+    Delay {
+        item: Item,
+    },
+    // Delayed items due at or before `now` moved into the queue
+    Deliver {
+        queue: String,
+        now: i64,
+    },
+    // Oldest `count` items removed from a queue
+    Remove {
+        queue: String,
+        count: usize,
+    },
+    // Ready items with the given ids removed
+    RemoveIds {
+        queue: String,
+        ids: Vec<String>,
+    },
+    // Item removed by id, whether it is ready, leased or delayed
+    Delete {
+        queue: String,
+        id: String,
+    },
+    // Items, ready, leased or delayed, that expired at or before `now` removed
+    Expire {
+        queue: String,
+        now: i64,
+    },
+    // Items, ready, leased or delayed, received before `before` removed, or every item if it is not set
+    Purge {
+        queue: String,
+        before: Option<i64>,
+    },
+    // Oldest `count` items moved into a lease
+    Lease {
+        queue: String,
+        receipt: String,
+        count: usize,
+        expires_epoch: i64,
+    },
+    // Ready items with the given ids moved into a lease
+    LeaseIds {
+        queue: String,
+        receipt: String,
+        ids: Vec<String>,
+        expires_epoch: i64,
+    },
+    // Lease restored as it was when the log was compacted, its deliveries already counted
+    Leased {
+        queue: String,
+        lease: Lease,
+    },
+    // Leased items acknowledged and removed
+    Ack {
+        queue: String,
+        receipt: String,
+    },
+    // Leased items returned to the front of the queue
+    Release {
+        queue: String,
+        receipt: String,
+    },
+    // Item at `index` moved to the end of dead-letter queue `to`
+    DeadLetter {
+        queue: String,
+        index: usize,
+        to: String,
+    },
+    // Every item dead-lettered from `to` moved back to it
+    Redrive {
+        queue: String,
+        to: String,
+    },
+}
+
+// Append-only log of queue operations, replayed on startup
+pub struct Wal {
+    path: PathBuf,
+    file: File,
+    fsync: FsyncPolicy,
+    dirty: bool,
+}
+
+impl Wal {
+    // Opens (or creates) the log at `path`, returning it alongside the replayed queues
+    pub fn open(
+        path: PathBuf,
+        fsync: FsyncPolicy,
+    ) -> io::Result<(Wal, HashMap<String, QueueState>)> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let queues = if path.exists() {
+            replay(&path)?
+        } else {
+            HashMap::new()
+        };
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut wal = Wal {
+            path,
+            file,
+            fsync,
+            dirty: false,
+        };
+        // Rewrite the log so a torn trailing record from a crash is not appended to
+        wal.compact(queues.iter().map(|(name, state)| (name.as_str(), state)))?;
+
+        Ok((wal, queues))
+    }
+
+    // Appends a record, syncing to disk if the policy requires it
+    pub fn append(&mut self, record: &WalRecord) -> io::Result<()> {
+        write_record(&mut self.file, record)?;
+
+        match self.fsync {
+            FsyncPolicy::Always => self.file.sync_data()?,
+            FsyncPolicy::Interval => self.dirty = true,
+            FsyncPolicy::Never => {}
+        }
+        Ok(())
+    }
+
+    // Flushes any unsynced records to disk
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.dirty {
+            self.file.sync_data()?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    // Replaces the log with the minimum records needed to rebuild the given queues
+    pub fn compact<'a>(
+        &mut self,
+        queues: impl Iterator<Item = (&'a str, &'a QueueState)>,
+    ) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("wal.tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        for (queue, state) in queues {
+            // Leases are restored whole, so replaying them does not count their items' delivery again
+            for lease in state.leases.values() {
+                write_record(
+                    &mut tmp,
+                    &WalRecord::Leased {
+                        queue: queue.to_string(),
+                        lease: lease.clone(),
+                    },
+                )?;
+            }
+            for item in &state.items {
+                write_record(&mut tmp, &WalRecord::Push { item: item.clone() })?;
+            }
+            for item in &state.delayed {
+                write_record(&mut tmp, &WalRecord::Delay { item: item.clone() })?;
+            }
+        }
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+fn write_record(file: &mut File, record: &WalRecord) -> io::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    file.write_all(&line)
+}
+
+// Rebuilds queue contents by applying every record in the log in order
+fn replay(path: &Path) -> io::Result<HashMap<String, QueueState>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut queues: HashMap<String, QueueState> = HashMap::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = match serde_json::from_str::<WalRecord>(&line) {
+            Ok(r) => r,
+            // Most likely a partially written record from a crash, nothing after it is trusted
+            Err(e) => {
+                warn!(
+                    "Stopping WAL replay at line {}, invalid record: {}",
+                    i + 1,
+                    e
+                );
+                break;
+            }
+        };
+        apply(&mut queues, record);
+    }
+
+    queues.retain(|_, state| !state.is_empty());
+    info!(
+        "Replayed {} items in {} queues from WAL '{}'",
+        queues.values().map(QueueState::len).sum::<usize>(),
+        queues.len(),
+        path.display()
+    );
+    Ok(queues)
+}
+
+// Applies a single record to the queues being rebuilt
+fn apply(queues: &mut HashMap<String, QueueState>, record: WalRecord) {
+    match record {
+        WalRecord::Batch { records } => {
+            for record in records {
+                apply(queues, record);
+            }
+        }
+        WalRecord::Push { item } => queues.entry(item.queue.clone()).or_default().push(item),
+        WalRecord::Delay { item } => queues.entry(item.queue.clone()).or_default().delay(item),
+        WalRecord::Deliver { queue, now } => {
+            if let Some(state) = queues.get_mut(&queue) {
+                state.deliver(now);
+            }
+        }
+        WalRecord::Remove { queue, count } => {
+            if let Some(state) = queues.get_mut(&queue) {
+                state.remove_front(count);
+            }
+        }
+        WalRecord::RemoveIds { queue, ids } => {
+            if let Some(state) = queues.get_mut(&queue) {
+                state.remove_ids(&ids);
+            }
+        }
+        WalRecord::Delete { queue, id } => {
+            if let Some(state) = queues.get_mut(&queue) {
+                state.delete(&id);
+            }
+        }
+        WalRecord::Expire { queue, now } => {
+            if let Some(state) = queues.get_mut(&queue) {
+                state.expire(now);
+            }
+        }
+        WalRecord::Purge { queue, before } => {
+            if let Some(state) = queues.get_mut(&queue) {
+                state.purge(before);
+            }
+        }
+        WalRecord::Lease {
+            queue,
+            receipt,
+            count,
+            expires_epoch,
+        } => {
+            if let Some(state) = queues.get_mut(&queue) {
+                state.lease_front(count, receipt, expires_epoch);
+            }
+        }
+        WalRecord::LeaseIds {
+            queue,
+            receipt,
+            ids,
+            expires_epoch,
+        } => {
+            if let Some(state) = queues.get_mut(&queue) {
+                state.lease_ids(&ids, receipt, expires_epoch);
+            }
+        }
+        WalRecord::Leased { queue, lease } => queues.entry(queue).or_default().restore_lease(lease),
+        WalRecord::Ack { queue, receipt } => {
+            if let Some(state) = queues.get_mut(&queue) {
+                state.ack(&receipt);
+            }
+        }
+        WalRecord::Release { queue, receipt } => {
+            if let Some(state) = queues.get_mut(&queue) {
+                state.release(&receipt);
+            }
+        }
+        WalRecord::DeadLetter { queue, index, to } => {
+            let item = queues
+                .get_mut(&queue)
+                .and_then(|state| state.dead_letter_at(index, &to));
+            if let Some(item) = item {
+                queues.entry(to).or_default().push(item);
+            }
+        }
+        WalRecord::Redrive { queue, to } => {
+            let items = queues
+                .get_mut(&queue)
+                .map(|state| state.take_dead_letters(&to))
+                .unwrap_or_default();
+            let target = queues.entry(to).or_default();
+            for item in items {
+                target.push(item);
+            }
+        }
+    }
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
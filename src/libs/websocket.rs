@@ -0,0 +1,268 @@
+use std::{collections::HashMap, time::Duration};
+
+use actix_web::web;
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use chrono::Utc;
+use futures_util::StreamExt as _;
+use log::{debug, error};
+use serde::Serialize;
+use tokio::{pin, select, time::timeout};
+
+use crate::libs::{
+    routes::publish_fetched,
+    structs::{AppState, WebError, WsFrame, WsQuery},
+};
+
+// Subscriptions check their queue at least this often, whether or not they are woken
+const RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+// Messages sent but not yet acknowledged when the client does not set `prefetch`
+const DEFAULT_PREFETCH: usize = 100;
+// Seconds items are leased for while they are sent, or until acknowledged by id when `visibility_timeout`
+// is not set, before they are returned to the queue
+const ACK_TIMEOUT_SECONDS: i64 = 30;
+
+// Open subscription to a queue, pushing items to a WebSocket client as they arrive
+struct Subscription {
+    data: web::Data<AppState>,
+    queue: String,
+    options: WsQuery,
+    session: Session,
+    // Messages sent but not yet acknowledged, by receipt or item id, with the receipt of their lease and when
+    // it times out
+    in_flight: HashMap<String, (String, i64)>,
+}
+
+// Runs a subscription until either side closes it
+pub async fn subscribe(
+    data: web::Data<AppState>,
+    queue: String,
+    options: WsQuery,
+    session: Session,
+    mut messages: MessageStream,
+) {
+    let notifier = data.notifiers.wait(&queue);
+    let mut subscription = Subscription {
+        data,
+        queue,
+        options,
+        session,
+        in_flight: HashMap::new(),
+    };
+    debug!("Subscription to queue '{}' opened", subscription.queue);
+
+    let reason = loop {
+        let notified = notifier.notified();
+        pin!(notified);
+        // Registered before checking the queue, so an item added in between still wakes this subscription
+        notified.as_mut().enable();
+
+        if let Err(reason) = subscription.push_available().await {
+            break reason;
+        }
+
+        select! {
+            message = messages.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    if let Err(reason) = subscription.handle_frame(&text).await {
+                        break reason;
+                    }
+                }
+                Some(Ok(Message::Ping(bytes))) => {
+                    if subscription.session.pong(&bytes).await.is_err() {
+                        break None;
+                    }
+                }
+                Some(Ok(Message::Close(reason))) => break reason,
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break None,
+            },
+            _ = timeout(RECHECK_INTERVAL, notified) => {}
+        }
+    };
+
+    debug!("Subscription to queue '{}' closed", subscription.queue);
+    subscription.release_unacknowledged();
+    let _ = subscription.session.close(reason).await;
+}
+
+impl Subscription {
+    // Sends as many items as the client has room for. Errors carry the reason to close the session with
+    async fn push_available(&mut self) -> Result<(), Option<CloseReason>> {
+        // Leases that timed out no longer need acknowledging, their items are back in the queue
+        let now = Utc::now().timestamp();
+        self.in_flight
+            .retain(|_, (_, expires_epoch)| *expires_epoch > now);
+
+        let room = self
+            .options
+            .prefetch
+            .unwrap_or(DEFAULT_PREFETCH)
+            .saturating_sub(self.in_flight.len());
+        if room == 0 {
+            return Ok(());
+        }
+        let dead_letter = self.data.dead_letter(&self.queue);
+
+        // Without acknowledgements, items are leased until their frames are written and only removed then,
+        // so none are lost if the client disconnects first
+        if !self.acknowledged() {
+            let lease = match self.data.storage.lease(
+                &self.queue,
+                self.data.batch_limit(Some(room)),
+                ACK_TIMEOUT_SECONDS,
+                dead_letter.as_ref(),
+                None,
+            ) {
+                Ok(Some(lease)) => lease,
+                Ok(None) => return Ok(()),
+                Err(e) => return Err(storage_error(e)),
+            };
+            publish_fetched(&self.data, &self.queue, &lease.items);
+            for item in &lease.items {
+                if let Err(reason) = self.send(item).await {
+                    self.release(&lease.receipt);
+                    return Err(reason);
+                }
+            }
+            return match self.data.storage.ack(&self.queue, &lease.receipt) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(storage_error(e)),
+            };
+        }
+
+        // Each item is leased on its own, so it can be acknowledged on its own
+        let visibility_timeout = self
+            .options
+            .visibility_timeout
+            .unwrap_or(ACK_TIMEOUT_SECONDS);
+        for _ in 0..room {
+            let lease = match self.data.storage.lease(
+                &self.queue,
+                Some(1),
+                visibility_timeout,
+                dead_letter.as_ref(),
+                None,
+            ) {
+                Ok(Some(lease)) => lease,
+                Ok(None) => break,
+                Err(e) => return Err(storage_error(e)),
+            };
+            publish_fetched(&self.data, &self.queue, &lease.items);
+            let in_flight = (lease.receipt.clone(), lease.expires_epoch);
+            if self.options.visibility_timeout.is_some() {
+                self.in_flight.insert(lease.receipt.clone(), in_flight);
+                self.send(&lease).await?;
+            } else if let Some(item) = lease.items.first() {
+                self.in_flight
+                    .insert(item.id().unwrap_or_default().to_string(), in_flight);
+                self.send(item).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Applies an acknowledgement frame from the client
+    async fn handle_frame(&mut self, text: &str) -> Result<(), Option<CloseReason>> {
+        let frame = match serde_json::from_str::<WsFrame>(text) {
+            Ok(frame) => frame,
+            Err(e) => return self.send_error(format!("invalid frame. {}", e)).await,
+        };
+
+        let leased = self.options.visibility_timeout.is_some();
+        let settled = match frame {
+            WsFrame::Ack(key) if leased => {
+                self.in_flight.remove(&key);
+                self.data.storage.ack(&self.queue, &key)
+            }
+            WsFrame::Ack(key) => match self.in_flight.remove(&key) {
+                Some((receipt, _)) => self.data.storage.ack(&self.queue, &receipt),
+                None => Ok(false),
+            },
+            WsFrame::Nack(key) if leased => {
+                self.in_flight.remove(&key);
+                let released = self.data.storage.nack(&self.queue, &key);
+                if let Ok(true) = released {
+                    self.data.notifiers.notify(&self.queue);
+                }
+                released
+            }
+            WsFrame::Nack(_) => {
+                return self
+                    .send_error("nack requires visibility_timeout".to_string())
+                    .await
+            }
+        };
+
+        match settled {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                self.send_error("nothing to acknowledge, it may have timed out".to_string())
+                    .await
+            }
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    // True if the client acknowledges messages, by receipt with `visibility_timeout` or by item id with only `prefetch`
+    fn acknowledged(&self) -> bool {
+        self.options.visibility_timeout.is_some() || self.options.prefetch.is_some()
+    }
+
+    // Returns a lease's items to the queue, so another consumer can have them
+    fn release(&self, receipt: &str) {
+        match self.data.storage.nack(&self.queue, receipt) {
+            Ok(true) => self.data.notifiers.notify(&self.queue),
+            Ok(false) => {}
+            Err(e) => error!("Failed to release lease of queue '{}': {}", self.queue, e),
+        }
+    }
+
+    // Returns items acknowledged by id that never were to the queue. Leases the client was sent can still be
+    // acknowledged by receipt until they time out
+    fn release_unacknowledged(&mut self) {
+        if self.options.visibility_timeout.is_some() {
+            return;
+        }
+        for (_, (receipt, _)) in std::mem::take(&mut self.in_flight) {
+            self.release(&receipt);
+        }
+    }
+
+    async fn send(&mut self, message: &impl Serialize) -> Result<(), Option<CloseReason>> {
+        let text = serde_json::to_string(message).map_err(|e| storage_error(e.into()))?;
+        self.session.text(text).await.map_err(|_| None)
+    }
+
+    async fn send_error(&mut self, error: String) -> Result<(), Option<CloseReason>> {
+        self.send(&WebError {
+            timestamp: Utc::now().to_rfc3339(),
+            error,
+        })
+        .await
+    }
+}
+
+fn storage_error(e: std::io::Error) -> Option<CloseReason> {
+    error!("Storage operation failed: {}", e);
+    Some(CloseReason {
+        code: CloseCode::Error,
+        description: Some("storage operation failed".to_string()),
+    })
+}
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
@@ -1,8 +1,13 @@
-mod libs;
-use libs::{
-    routes,
-    structs::{CargoPkgInfo, Item, Meta, TOMLData, WebError, WebHealth},
-    utils::draw_start_screen,
+use conga::libs::{
+    routes::{self, DEFAULT_MAX_BULK_PAYLOAD_SIZE, DEFAULT_MAX_PAYLOAD_SIZE},
+    structs::{
+        BindingConfig, CargoPkgInfo, Item, Lease, Meta, OverflowPolicy, PreviewOrder, QueueConfig,
+        TOMLData, WebBulkError, WebEntryError, WebError, WebExchange, WebExchangeItem,
+        WebGroupOffset, WebGroupStats, WebHealth, WebItemId, WebItemIds, WebPublished, WebPurge,
+        WebQueueDefinition, WebQueueStats, WebReceipt, WebRedrive, WebRouted, WebRoutedItem,
+        WebTopicItem, WebTopicStats, WebValidationError, WebViolation,
+    },
+    utils::{draw_start_screen, validate_config},
 };
 
 use actix_cors::Cors;
@@ -13,7 +18,7 @@ use actix_web::{
 };
 use chrono::Utc;
 use dotenv::dotenv;
-use log::{debug, info, LevelFilter};
+use log::{debug, error, info, LevelFilter};
 use simplelog::*;
 use utoipa::{
     openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
@@ -22,11 +27,23 @@ use utoipa::{
 use utoipa_swagger_ui::SwaggerUi;
 
 use std::fs::File;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::vec;
-use std::{env, str::FromStr};
+use std::{env, path::PathBuf, process::exit, str::FromStr};
 
-use crate::libs::{structs::AppState, utils::load_config_toml};
+use conga::libs::{
+    dedup::{Dedup, DEFAULT_DEDUP_WINDOW},
+    definitions::{QueueDefinitions, DEFAULT_DEFINITIONS_FILE},
+    events::{Events, DEFAULT_EVENT_HISTORY},
+    exchanges::Exchanges,
+    expiry::{spawn_expiry_task, DEFAULT_EXPIRY_INTERVAL},
+    notifier::{Notifiers, DEFAULT_MAX_WAIT},
+    schemas::{spawn_reload_task, Schemas, DEFAULT_SCHEMA_RELOAD_INTERVAL},
+    storage::open_storage,
+    structs::{AppState, ExpiredTotals},
+    topics::{spawn_retention_task, Topics, DEFAULT_TOPICS_FILE},
+    utils::load_config_toml,
+};
 
 const DATA_FOLDER: &str = "config/";
 
@@ -40,11 +57,36 @@ async fn main() -> std::io::Result<()> {
             routes::health,
             routes::auth,
             routes::add_item,
+            routes::add_items,
             routes::get_items,
-            routes::fetch_items
+            routes::get_delayed_items,
+            routes::fetch_items,
+            routes::get_item,
+            routes::delete_item,
+            routes::ack_items,
+            routes::nack_items,
+            routes::redrive_items,
+            routes::subscribe,
+            routes::stream_events,
+            routes::list_queues,
+            routes::get_queue,
+            routes::purge_queue,
+            routes::list_definitions,
+            routes::get_definition,
+            routes::set_definition,
+            routes::delete_definition,
+            routes::get_schema,
+            routes::list_topics,
+            routes::get_topic,
+            routes::publish_item,
+            routes::read_topic,
+            routes::set_group,
+            routes::delete_group,
+            routes::list_exchanges,
+            routes::publish_to_exchange
         ),
         components(
-            schemas(WebHealth, WebError, Meta, Item)
+            schemas(WebHealth, WebError, WebItemId, WebItemIds, WebEntryError, WebBulkError, WebReceipt, WebRedrive, WebQueueStats, WebPurge, WebQueueDefinition, WebValidationError, WebViolation, WebTopicItem, WebPublished, WebTopicStats, WebGroupStats, WebGroupOffset, WebExchange, WebExchangeItem, WebRouted, WebRoutedItem, BindingConfig, QueueConfig, OverflowPolicy, PreviewOrder, Meta, Item, Lease)
         ),
         tags(),
         modifiers(&SecurityAddon)
@@ -65,7 +107,74 @@ async fn main() -> std::io::Result<()> {
     // Make instance variable of ApiDoc so all worker threads gets the same instance.
     let openapi = ApiDoc::openapi();
 
-    let queue = Arc::new(Mutex::new(Vec::<Item>::new()));
+    let storage = open_storage(&toml_data.config)?;
+    let expired = ExpiredTotals::default();
+    let notifiers = Arc::new(Notifiers::default());
+    let dedup = Arc::new(Dedup::default());
+    let definitions = Arc::new(QueueDefinitions::load(
+        toml_data.clone().queues.unwrap_or_default(),
+        PathBuf::from(
+            toml_data
+                .config
+                .definitions_file
+                .as_deref()
+                .unwrap_or(DEFAULT_DEFINITIONS_FILE),
+        ),
+    )?);
+    let schemas = match Schemas::load(PathBuf::from(DATA_FOLDER), &definitions) {
+        Ok(schemas) => Arc::new(schemas),
+        Err(e) => {
+            error!("Invalid schema, {}", e);
+            exit(1);
+        }
+    };
+    spawn_reload_task(
+        schemas.clone(),
+        toml_data
+            .config
+            .schema_reload_interval
+            .unwrap_or(DEFAULT_SCHEMA_RELOAD_INTERVAL),
+    );
+    let topics = Arc::new(Topics::load(
+        storage.clone(),
+        toml_data.clone().topics.unwrap_or_default(),
+        PathBuf::from(
+            toml_data
+                .config
+                .topics_file
+                .as_deref()
+                .unwrap_or(DEFAULT_TOPICS_FILE),
+        ),
+    )?);
+    spawn_retention_task(
+        topics.clone(),
+        toml_data
+            .config
+            .expiry_interval
+            .unwrap_or(DEFAULT_EXPIRY_INTERVAL),
+    );
+    let exchanges = match Exchanges::load(toml_data.clone().exchanges.unwrap_or_default()) {
+        Ok(exchanges) => Arc::new(exchanges),
+        Err(e) => {
+            error!("Invalid exchange, {}", e);
+            exit(1);
+        }
+    };
+    let events = Arc::new(Events::new(
+        toml_data
+            .config
+            .event_history
+            .unwrap_or(DEFAULT_EVENT_HISTORY),
+    ));
+    spawn_expiry_task(
+        storage.clone(),
+        expired.clone(),
+        events.clone(),
+        toml_data
+            .config
+            .expiry_interval
+            .unwrap_or(DEFAULT_EXPIRY_INTERVAL),
+    );
 
     // Start Web
     let host: String = toml_data.clone().config.web_host;
@@ -74,11 +183,14 @@ async fn main() -> std::io::Result<()> {
     HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
-            .allowed_methods(vec!["POST", "GET"])
+            .allowed_methods(vec!["POST", "GET", "PUT", "DELETE"])
             .allowed_headers(vec![
                 http::header::AUTHORIZATION,
                 http::header::CONTENT_TYPE,
+                http::header::HeaderName::from_static("last-event-id"),
+                http::header::HeaderName::from_static("idempotency-key"),
             ])
+            .expose_headers(vec!["X-Total-Count", "X-Next-Offset", "Location"])
             .supports_credentials()
             .max_age(3600);
 
@@ -86,17 +198,64 @@ async fn main() -> std::io::Result<()> {
             .wrap(cors)
             .app_data(web::Data::new(AppState {
                 start_time: Utc::now(),
-                item_queue: queue.clone(),
-                api_keys: match toml_data.clone().config.api_keys {
-                    Some(keys) => keys,
-                    _ => vec![],
-                },
+                storage: storage.clone(),
+                api_keys: toml_data.clone().config.api_keys.unwrap_or_default(),
+                admin_api_keys: toml_data.clone().config.admin_api_keys.unwrap_or_default(),
+                max_batch_size: toml_data.config.max_batch_size,
+                max_payload_size: toml_data
+                    .config
+                    .max_payload_size
+                    .unwrap_or(DEFAULT_MAX_PAYLOAD_SIZE),
+                max_bulk_payload_size: toml_data
+                    .config
+                    .max_bulk_payload_size
+                    .unwrap_or(DEFAULT_MAX_BULK_PAYLOAD_SIZE),
+                max_memory_bytes: toml_data.config.max_memory_bytes,
+                max_wait: toml_data.config.max_wait.unwrap_or(DEFAULT_MAX_WAIT),
+                dedup_window: toml_data
+                    .config
+                    .dedup_window
+                    .unwrap_or(DEFAULT_DEDUP_WINDOW),
+                queues: definitions.clone(),
+                strict_queues: toml_data.config.strict_queues.unwrap_or(false),
+                expired: expired.clone(),
+                notifiers: notifiers.clone(),
+                events: events.clone(),
+                dedup: dedup.clone(),
+                schemas: schemas.clone(),
+                topics: topics.clone(),
+                exchanges: exchanges.clone(),
             }))
             .service(routes::auth)
             .service(routes::health)
             .service(routes::add_item)
+            .service(routes::add_items)
             .service(routes::get_items)
+            .service(routes::get_delayed_items)
             .service(routes::fetch_items)
+            .service(routes::get_item)
+            .service(routes::delete_item)
+            .service(routes::ack_items)
+            .service(routes::nack_items)
+            .service(routes::redrive_items)
+            .service(routes::subscribe)
+            .service(routes::stream_events)
+            .service(routes::list_queues)
+            .service(routes::get_queue)
+            .service(routes::purge_queue)
+            .service(routes::list_definitions)
+            .service(routes::get_definition)
+            .service(routes::set_definition)
+            .service(routes::delete_definition)
+            .service(routes::get_schema)
+            .service(routes::list_topics)
+            .service(routes::get_topic)
+            .service(routes::publish_item)
+            .service(routes::read_topic)
+            .service(routes::set_group)
+            .service(routes::delete_group)
+            .service(routes::list_exchanges)
+            .service(routes::publish_to_exchange)
             // Extras
             .service(
                 SwaggerUi::new("/swagger-ui/{_:.*}").url("/api-doc/openapi.json", openapi.clone()),
@@ -159,6 +318,7 @@ fn startup() -> TOMLData {
 
     // Config validation
     debug!("Config loaded:\n{:?}", toml_data.config);
+    validate_config(&toml_data);
 
     toml_data
 }
@@ -0,0 +1,227 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use serde_json::json;
+use uuid::Uuid;
+
+use conga::libs::{
+    storage::{memory::MemoryStorage, Storage},
+    structs::{FsyncPolicy, Item, Meta},
+};
+
+fn item(queue: &str, id: &str) -> Item {
+    Item {
+        queue: queue.to_string(),
+        content: json!({ "id": id }),
+        priority: None,
+        ttl: None,
+        delay_seconds: None,
+        deliver_at: None,
+        dedup_id: None,
+        exchange: None,
+        routing_key: None,
+        meta: Some(Meta {
+            id: id.to_string(),
+            received_epoch: 0,
+            receive_count: 0,
+            dead_letter_source: None,
+            expires_epoch: None,
+            deliver_epoch: None,
+            size: 16,
+            offset: None,
+        }),
+    }
+}
+
+// Directory for a test's log, removed by the test once it passes
+fn temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("conga-wal-{}", Uuid::new_v4()))
+}
+
+fn open(path: &Path) -> MemoryStorage {
+    MemoryStorage::with_wal(path.to_path_buf(), FsyncPolicy::Always).unwrap()
+}
+
+// Ids of a queue's ready items in delivery order, ids of its delayed items, and its item count and size
+fn snapshot(storage: &MemoryStorage, queue: &str) -> (Vec<String>, Vec<String>, usize, usize) {
+    let ids = |items: Vec<Item>| {
+        items
+            .iter()
+            .map(|item| item.id().unwrap().to_string())
+            .collect()
+    };
+    let usage = storage.usage(queue).unwrap();
+    (
+        ids(storage.preview(queue, 0, None, None).unwrap()),
+        ids(storage.delayed(queue, 0, None).unwrap()),
+        usage.items,
+        usage.bytes,
+    )
+}
+
+// Every kind of change is replayed from the log, leaving the queue as it was before the restart
+#[test]
+fn replay_restores_pushes_takes_delays_and_purges() {
+    let dir = temp_dir();
+    let path = dir.join("conga.wal");
+
+    let storage = open(&path);
+    let mut old = item("jobs", "old");
+    old.meta.as_mut().unwrap().received_epoch = 10;
+    storage.push(old).unwrap();
+    for id in ["a", "b", "c"] {
+        let mut item = item("jobs", id);
+        item.meta.as_mut().unwrap().received_epoch = 100;
+        storage.push(item).unwrap();
+    }
+    let mut urgent = item("jobs", "urgent");
+    urgent.priority = Some(5);
+    urgent.meta.as_mut().unwrap().received_epoch = 100;
+    storage.push(urgent).unwrap();
+    let mut later = item("jobs", "later");
+    later.meta.as_mut().unwrap().deliver_epoch = Some(Utc::now().timestamp() + 3600);
+    later.meta.as_mut().unwrap().received_epoch = 100;
+    storage.push(later).unwrap();
+
+    let taken = storage.take("jobs", Some(1), None).unwrap();
+    assert_eq!(taken[0].id(), Some("urgent"));
+    assert_eq!(storage.purge("jobs", Some(50)).unwrap(), 1);
+    assert!(storage.delete("jobs", "b").unwrap());
+
+    let before = snapshot(&storage, "jobs");
+    assert_eq!(before.0, ["a", "c"]);
+    assert_eq!(before.1, ["later"]);
+    drop(storage);
+
+    assert_eq!(snapshot(&open(&path), "jobs"), before);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// A record cut short by a crash ends replay without failing it, and is gone from the log afterwards
+#[test]
+fn replay_stops_at_torn_last_line() {
+    let dir = temp_dir();
+    let path = dir.join("conga.wal");
+
+    let storage = open(&path);
+    storage.push(item("jobs", "a")).unwrap();
+    storage.push(item("jobs", "b")).unwrap();
+    drop(storage);
+    let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(br#"{"op":"push","item":{"queue":"jobs","con"#)
+        .unwrap();
+    drop(file);
+
+    let storage = open(&path);
+    assert_eq!(snapshot(&storage, "jobs").0, ["a", "b"]);
+    // Records written after the restart are replayed too, rather than lost behind the torn one
+    storage.push(item("jobs", "c")).unwrap();
+    drop(storage);
+
+    assert_eq!(snapshot(&open(&path), "jobs").0, ["a", "b", "c"]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// Compaction on restart rewrites the log to one record per item and lease, which rebuild the same queues
+#[test]
+fn compaction_keeps_queue_state() {
+    let dir = temp_dir();
+    let path = dir.join("conga.wal");
+
+    let storage = open(&path);
+    for n in 0..20 {
+        let mut item = item(
+            if n % 2 == 0 { "jobs" } else { "mail" },
+            &format!("item-{n}"),
+        );
+        item.priority = Some(n % 3);
+        storage.push(item).unwrap();
+    }
+    let mut later = item("jobs", "later");
+    later.meta.as_mut().unwrap().deliver_epoch = Some(Utc::now().timestamp() + 3600);
+    storage.push(later).unwrap();
+    storage.take("jobs", Some(2), None).unwrap();
+    storage.purge("mail", None).unwrap();
+    storage.push(item("mail", "after-purge")).unwrap();
+    let lease = storage
+        .lease("jobs", Some(3), 3600, None, None)
+        .unwrap()
+        .unwrap();
+
+    let jobs = snapshot(&storage, "jobs");
+    let mail = snapshot(&storage, "mail");
+    drop(storage);
+
+    // Replays the full log, then compacts it
+    let storage = open(&path);
+    assert_eq!(snapshot(&storage, "jobs"), jobs);
+    assert_eq!(snapshot(&storage, "mail"), mail);
+    drop(storage);
+    let records = fs::read_to_string(&path).unwrap().lines().count();
+    assert_eq!(records, jobs.0.len() + jobs.1.len() + 1 + mail.0.len());
+
+    // Replays the compacted log
+    let storage = open(&path);
+    assert_eq!(snapshot(&storage, "jobs"), jobs);
+    assert_eq!(snapshot(&storage, "mail"), mail);
+    assert!(storage.ack("jobs", &lease.receipt).unwrap());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// Leases survive restarts, which compact the log each time, without their deliveries being counted again
+#[test]
+fn restart_keeps_receive_count_of_leased_items() {
+    let dir = temp_dir();
+    let path = dir.join("conga.wal");
+
+    let storage = open(&path);
+    storage.push(item("jobs", "leased")).unwrap();
+    storage.push(item("jobs", "ready")).unwrap();
+    let lease = storage
+        .lease("jobs", Some(1), 3600, None, None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(lease.items[0].receive_count(), 1);
+    drop(storage);
+
+    for _ in 0..3 {
+        let storage = open(&path);
+        let leased = storage.get("jobs", "leased").unwrap().unwrap();
+        assert_eq!(leased.receive_count(), 1);
+        let ready = storage.get("jobs", "ready").unwrap().unwrap();
+        assert_eq!(ready.receive_count(), 0);
+        assert_eq!(storage.usage("jobs").unwrap().bytes, 32);
+    }
+
+    // The lease is still held under its receipt, and can be acknowledged
+    let storage = open(&path);
+    assert!(storage.ack("jobs", &lease.receipt).unwrap());
+    assert_eq!(storage.count("jobs").unwrap(), 1);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/*
+########################################################################################################
+#   Copyright (C) 2022 Coombszy
+#
+#    This program is free software: you can redistribute it and/or modify
+#    it under the terms of the GNU General Public License as published by
+#    the Free Software Foundation, either version 3 of the License, or
+#    (at your option) any later version.
+#
+#    This program is distributed in the hope that it will be useful,
+#    but WITHOUT ANY WARRANTY; without even the implied warranty of
+#    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+#    GNU General Public License for more details.
+#
+#    You should have received a copy of the GNU General Public License
+#    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/